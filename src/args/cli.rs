@@ -0,0 +1,456 @@
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+
+use crate::{
+    structs::{config::config_layers::OVERRIDES, deps::Deps},
+    util::funcs::Interaction,
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "rranch", about = "The branch client rewritten in Rust with Protocol version 2 (json)", arg_required_else_help = true)]
+pub struct Cli {
+    /// Uses the named profile instead of default_profile, also read from RRANCH_PROFILE
+    #[arg(long, global = true, value_name = "name")]
+    pub profile: Option<String>,
+    /// Answers yes/no questions with yes and other prompts with their default, also read from RRANCH_YES
+    #[arg(long, short = 'y', global = true)]
+    pub yes: bool,
+    /// Never prompts, answers with the default or fails if there is none, also read from RRANCH_NO_INPUT
+    #[arg(long, global = true)]
+    pub no_input: bool,
+    /// Runs queries as usual but prints requests changing the master instead of sending them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Pkgbuilds on the master
+    #[command(subcommand)]
+    Pkgb(PkgbCommand),
+    /// Built packages
+    #[command(subcommand)]
+    Pkg(PkgCommand),
+    /// Build jobs
+    #[command(subcommand)]
+    Job(JobCommand),
+    /// Clients connected to the master
+    #[command(subcommand)]
+    Client(ClientCommand),
+    /// Extra sources on the master
+    #[command(subcommand, name = "extrasource", visible_alias = "es")]
+    ExtraSource(ExtraSourceCommand),
+    /// Local config
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Local package cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Pkgbuilds in a git repository
+    #[command(subcommand)]
+    Sync(SyncCommand),
+    /// Runs commands over one connection, with history and tab completion
+    Shell,
+    /// Runs commands from a file, one per line, over one connection
+    Batch {
+        #[arg(value_name = "file|-")]
+        file: String,
+        /// Runs the remaining commands after a failure instead of stopping
+        #[arg(long)]
+        continue_on_error: bool,
+    },
+    /// Converts a build recipe of another distribution into ./<name>/package.bpb, eg. `rranch convert --from arch PKGBUILD`
+    Convert {
+        #[arg(long)]
+        from: ConvertFrom,
+        path: String,
+        /// Overwrites an existing local copy without asking
+        #[arg(long)]
+        force: bool,
+    },
+    /// Prints a completion script, eg. `source <(rranch completions bash)`
+    Completions { shell: Shell },
+    /// Prints completion candidates fetched from the master, used by the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete { kind: CompletionKind },
+}
+
+// formats convert reads
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ConvertFrom {
+    /// Arch Linux PKGBUILD
+    Arch,
+}
+
+// values completed from the master
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CompletionKind {
+    Pkg,
+    Job,
+    Extrasource,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PkgbCommand {
+    /// Fetches a pkgbuild into ./<name>/package.bpb
+    Checkout {
+        name: String,
+        /// Overwrites an existing local copy without asking
+        #[arg(long)]
+        force: bool,
+    },
+    /// Shows the changes against the master's pkgbuild and submits it
+    Submit { path: String },
+    /// Creates a pkgbuild from a template
+    New(NewArgs),
+    /// Opens a pkgbuild from the master with the editor
+    Edit { name: String },
+    /// Opens a local pkgbuild with the editor
+    EditLocal { path: String },
+    /// Shows a diff of a local pkgbuild against the master's copy
+    Diff { path: String },
+    /// Sets a new version on a pkgbuild and submits it
+    Bump {
+        name: String,
+        version: String,
+        /// Queues a releasebuild after submitting
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Lists templates
+    Templates,
+    /// Shows pkgbuild status
+    List,
+    /// Exports all pkgbuilds
+    Export {
+        /// Overwrites existing local copies without asking
+        #[arg(long)]
+        force: bool,
+        /// Writes a tar.zst bundle with a manifest instead of a directory per pkgbuild
+        #[arg(long, value_name = "file")]
+        bundle: Option<String>,
+        /// Adds every extrasource to the bundle
+        #[arg(long, requires = "bundle")]
+        with_extrasources: bool,
+    },
+    /// Imports all pkgbuilds from a directory or a bundle
+    Import {
+        #[arg(required_unless_present = "bundle")]
+        path: Option<String>,
+        /// Imports a bundle written by export --bundle, recreating its extrasources first
+        #[arg(long, value_name = "file", conflicts_with = "path")]
+        bundle: Option<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct NewArgs {
+    pub name: String,
+    /// Template instead of asking
+    #[arg(long, value_name = "name")]
+    pub template: Option<String>,
+    /// Fills the {{key}} placeholder of the template
+    #[arg(long, value_name = "key=value", value_parser = parse_key_value)]
+    pub set: Vec<(String, String)>,
+    /// Source url, also used to guess the template and version
+    #[arg(long, value_name = "url")]
+    pub source: Option<String>,
+    /// Version, guessed from --source if not given
+    #[arg(long)]
+    pub version: Option<String>,
+    #[arg(long, value_name = "text")]
+    pub description: Option<String>,
+    /// Runtime dependencies
+    #[arg(long, value_name = "a,b", value_delimiter = ',')]
+    pub deps: Vec<String>,
+    /// Build dependencies
+    #[arg(long, value_name = "a,b", value_delimiter = ',')]
+    pub build_deps: Vec<String>,
+    /// Writes the pkgbuild without opening the editor
+    #[arg(long)]
+    pub no_edit: bool,
+    /// Submits right away, only with --no-edit
+    #[arg(long, requires = "no_edit")]
+    pub submit_now: bool,
+    /// Overwrites an existing local pkgbuild without asking
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PkgCommand {
+    /// Queues a build
+    Build {
+        name: String,
+        /// Crossbuilds instead of a releasebuild
+        #[arg(long)]
+        cross: bool,
+    },
+    /// Downloads a package
+    Fetch {
+        name: String,
+        /// Directory to write to instead of the current one
+        #[arg(long, value_name = "dir")]
+        output: Option<String>,
+        /// Checks the integrity of the package and its metadata against the pkgbuild
+        #[arg(long)]
+        verify: bool,
+        /// Also fetches the runtime dependency closure
+        #[arg(long)]
+        with_deps: bool,
+    },
+    /// Lists the contents of a package tarball
+    Inspect {
+        #[arg(value_name = "file|name")]
+        target: String,
+    },
+    /// Tries to find packages with a name
+    Find { name: String },
+    /// Shows package info
+    Info { name: String },
+    /// Removes a package
+    Remove { name: String },
+    /// Shows package status
+    List,
+    /// Shows packages and pkgbuilds missing their counterpart
+    Diff,
+    /// Shows dependers
+    Dependers { name: String },
+    /// Shows dependencies
+    Dependencies { name: String },
+    /// Rebuilds dependers
+    RebuildDependers { name: String },
+    /// Rebuilds dependencies
+    RebuildDependencies {
+        name: String,
+        #[arg(long, value_enum, default_value = "runtime")]
+        kind: DepsKind,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum DepsKind {
+    Runtime,
+    Build,
+    Cross,
+}
+
+impl From<&DepsKind> for Deps {
+    fn from(kind: &DepsKind) -> Self {
+        match kind {
+            DepsKind::Runtime => Deps::Runtime,
+            DepsKind::Build => Deps::Build,
+            DepsKind::Cross => Deps::Cross,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum JobCommand {
+    /// Shows jobs
+    Status,
+    /// Shows jobs periodically
+    Watch {
+        #[arg(default_value = "1", value_name = "seconds")]
+        interval: String,
+    },
+    /// Follows the log of a job
+    Log { id: String },
+    /// Shows the log of the latest job
+    Latest {
+        /// Latest running job instead of the latest completed one
+        #[arg(long)]
+        running: bool,
+    },
+    /// Shows the master's syslog
+    SysLog,
+    /// Clears completed jobs
+    ClearCompleted,
+    /// Cancels a queued job
+    Cancel {
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Cancels all queued jobs
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+    /// Submits a solution
+    Solution {
+        path: String,
+        /// Crossbuilds instead of releasebuilds
+        #[arg(long)]
+        cross: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ClientCommand {
+    /// Shows active clients
+    Status,
+    /// Shows client info
+    Info { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExtraSourceCommand {
+    /// Shows extrasources and the pkgbuilds using them
+    List,
+    /// Shows unused extrasources and references to missing ones
+    Unused,
+    /// Removes unused extrasources
+    Prune,
+    /// Removes an extrasource
+    Remove { id: String },
+    /// Submits an extrasource
+    Submit {
+        path: String,
+        /// Description instead of asking
+        #[arg(long, value_name = "text")]
+        description: Option<String>,
+    },
+    /// Submits every file in a directory or toml manifest
+    SubmitAll {
+        #[arg(value_name = "dir|manifest")]
+        path: String,
+    },
+    /// Downloads an extrasource
+    Fetch {
+        #[arg(value_name = "id|filename")]
+        id: String,
+    },
+    /// Downloads all extrasources of a pkgbuild
+    FetchFor { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Sets up the config interactively
+    Init,
+    /// Opens the config with the editor
+    Edit,
+    /// Shows the effective config
+    Show {
+        /// Shows where each value comes from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Lists master profiles
+    Profiles,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Lists cached packages
+    Ls,
+    /// Prunes the cache to its max size
+    Prune,
+    /// Clears the cache
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SyncCommand {
+    /// Writes the master's pkgbuilds into the repo and commits the changes
+    Pull {
+        #[arg(value_name = "repo-dir")]
+        repo: String,
+        /// Overwrites pkgbuilds also changed in the repo
+        #[arg(long)]
+        force: bool,
+    },
+    /// Submits the pkgbuilds changed in the repo since the last push
+    Push {
+        #[arg(value_name = "repo-dir")]
+        repo: String,
+        /// Submits pkgbuilds also changed on the master
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Cli {
+    pub fn get_interaction(&self) -> Option<Interaction> {
+        Interaction::from_flags(self.yes, self.no_input)
+    }
+}
+
+impl Command {
+    // runs without connecting to the master
+    pub fn is_local(&self) -> bool {
+        matches!(self, Command::Config(_) | Command::Cache(_) | Command::Pkgb(PkgbCommand::Templates) | Command::Convert { .. } | Command::Completions { .. })
+    }
+
+    pub fn opens_editor(&self) -> bool {
+        matches!(
+            self,
+            Command::Config(ConfigCommand::Edit)
+                | Command::Pkgb(PkgbCommand::Edit { .. })
+                | Command::Pkgb(PkgbCommand::EditLocal { .. })
+                | Command::Pkgb(PkgbCommand::New(NewArgs { no_edit: false, .. }))
+        )
+    }
+
+    // commands used to fix a broken config
+    pub fn fixes_config(&self) -> bool {
+        matches!(self, Command::Config(ConfigCommand::Init | ConfigCommand::Edit | ConfigCommand::Show { .. }))
+    }
+}
+
+// the derived command with the config overrides as global flags
+pub fn command() -> clap::Command {
+    Cli::command().args(OVERRIDES.iter().map(|ov| {
+        clap::Arg::new(ov.long)
+            .long(ov.long)
+            .global(true)
+            .help_heading("Config overrides")
+            .value_name(ov.get_param())
+            .help(format!("Overrides {}, also read from {}", ov.key, ov.get_env()))
+    }))
+}
+
+// a command with the config overrides passed as flags
+pub struct Parsed {
+    pub cli: Cli,
+    pub overrides: Vec<(String, String)>,
+    // subcommand path for messages, eg. pkgb checkout
+    pub name: String,
+}
+
+// parses argv, exits with usage on errors
+pub fn parse(args: Vec<String>) -> Parsed {
+    try_parse(args).unwrap_or_else(|err| err.exit())
+}
+
+pub fn try_parse(args: Vec<String>) -> Result<Parsed, clap::Error> {
+    let matches = command().try_get_matches_from(args)?;
+    let cli = Cli::from_arg_matches(&matches)?;
+
+    let mut path = Vec::new();
+    let mut current = &matches;
+    while let Some((name, sub)) = current.subcommand() {
+        path.push(name);
+        current = sub;
+    }
+    Ok(Parsed {
+        cli,
+        overrides: get_overrides(&matches),
+        name: path.join(" "),
+    })
+}
+
+fn get_overrides(matches: &ArgMatches) -> Vec<(String, String)> {
+    OVERRIDES
+        .iter()
+        .filter_map(|ov| matches.get_one::<String>(ov.long).map(|value| (format!("--{}", ov.long), value.clone())))
+        .collect()
+}
+
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_owned(), value.to_owned())),
+        None => Err(format!("expected key=value, got {pair}")),
+    }
+}
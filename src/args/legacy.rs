@@ -0,0 +1,251 @@
+use crate::structs::config::config_layers::OVERRIDES;
+
+use super::cli::command;
+
+// a flag of the old flag based interface and the subcommand it runs now
+struct Legacy {
+    short: &'static str,
+    long: &'static str,
+    command: &'static [&'static str],
+    value: bool,
+    // modifiers passed as positional values instead of flags, eg. --version for bump
+    positional: &'static [&'static str],
+}
+
+const fn legacy(short: &'static str, long: &'static str, command: &'static [&'static str], value: bool) -> Legacy {
+    Legacy {
+        short,
+        long,
+        command,
+        value,
+        positional: &[],
+    }
+}
+
+const COMMANDS: &[Legacy] = &[
+    legacy("c", "checkout", &["pkgb", "checkout"], true),
+    legacy("s", "submit", &["pkgb", "submit"], true),
+    legacy("n", "new", &["pkgb", "new"], true),
+    Legacy {
+        short: "bp",
+        long: "bump",
+        command: &["pkgb", "bump"],
+        value: true,
+        positional: &["version"],
+    },
+    legacy("tl", "templates", &["pkgb", "templates"], false),
+    legacy("dpb", "diff-pkgb", &["pkgb", "diff"], true),
+    legacy("e", "edit", &["pkgb", "edit"], true),
+    legacy("el", "edit-local", &["pkgb", "edit-local"], true),
+    legacy("mpkgb", "managed-pkgbs", &["pkgb", "list"], false),
+    legacy("ex", "export", &["pkgb", "export"], false),
+    legacy("im", "import", &["pkgb", "import"], true),
+    legacy("rb", "releasebuild", &["pkg", "build"], true),
+    legacy("cb", "crossbuild", &["pkg", "build", "--cross"], true),
+    legacy("depds", "dependers", &["pkg", "dependers"], true),
+    legacy("deps", "dependencies", &["pkg", "dependencies"], true),
+    legacy("rd", "rebuild-dependers", &["pkg", "rebuild-dependers"], true),
+    legacy("rds", "rebuild-dependencies", &["pkg", "rebuild-dependencies", "--kind", "runtime"], true),
+    legacy("rrds", "rebuild-build-dependencies", &["pkg", "rebuild-dependencies", "--kind", "build"], true),
+    legacy("rcds", "rebuild-cross-dependencies", &["pkg", "rebuild-dependencies", "--kind", "cross"], true),
+    legacy("mpkg", "managed-pkgs", &["pkg", "list"], false),
+    legacy("d", "diff", &["pkg", "diff"], false),
+    legacy("rm", "remove-pkg", &["pkg", "remove"], true),
+    legacy("fp", "fetch-pkg", &["pkg", "fetch"], true),
+    legacy("pi", "pkg-inspect", &["pkg", "inspect"], true),
+    legacy("f", "find", &["pkg", "find"], true),
+    legacy("i", "info", &["pkg", "info"], true),
+    legacy("jl", "job-log", &["job", "log"], true),
+    legacy("sl", "sys-log", &["job", "sys-log"], false),
+    legacy("js", "job-status", &["job", "status"], false),
+    legacy("wj", "watch-jobs", &["job", "watch"], true),
+    legacy("llc", "latest-log-complete", &["job", "latest"], false),
+    legacy("llr", "latest-log-running", &["job", "latest", "--running"], false),
+    legacy("cc", "clear-completed", &["job", "clear-completed"], false),
+    legacy("cq", "cancel-queued", &["job", "cancel"], true),
+    legacy("caq", "cancel-all-queued", &["job", "cancel", "--all"], false),
+    legacy("ssr", "submit-solution-release", &["job", "solution"], true),
+    legacy("ssc", "submit-solution-cross", &["job", "solution", "--cross"], true),
+    legacy("cs", "client-status", &["client", "status"], false),
+    legacy("ci", "client-info", &["client", "info"], true),
+    legacy("es", "extrasources", &["extrasource", "list"], false),
+    legacy("ues", "unused-extrasources", &["extrasource", "unused"], false),
+    legacy("pes", "prune-extrasources", &["extrasource", "prune"], false),
+    legacy("res", "remove-extrasource", &["extrasource", "remove"], true),
+    legacy("ses", "submit-extrasource", &["extrasource", "submit"], true),
+    legacy("sess", "submit-extrasources", &["extrasource", "submit-all"], true),
+    legacy("fes", "fetch-extrasource", &["extrasource", "fetch"], true),
+    legacy("fesa", "fetch-extrasources-for", &["extrasource", "fetch-for"], true),
+    legacy("in", "init", &["config", "init"], false),
+    legacy("cf", "configure", &["config", "edit"], false),
+    legacy("cfs", "config-show", &["config", "show"], false),
+    legacy("pfs", "profiles", &["config", "profiles"], false),
+    legacy("cls", "cache-ls", &["cache", "ls"], false),
+    legacy("cpr", "cache-prune", &["cache", "prune"], false),
+    legacy("ccl", "cache-clear", &["cache", "clear"], false),
+];
+
+// old flags modifying a command, they keep their long name as subcommand flags
+const MODIFIERS: &[(&str, &str, bool)] = &[
+    ("t", "template", true),
+    ("st", "set", true),
+    ("src", "source", true),
+    ("ver", "version", true),
+    ("dsc", "description", true),
+    ("dp", "deps", true),
+    ("bdp", "build-deps", true),
+    ("ne", "no-edit", false),
+    ("sn", "submit-now", false),
+    ("rbl", "rebuild", false),
+    ("o", "output", true),
+    ("vf", "verify", false),
+    ("wd", "with-deps", false),
+    ("or", "origin", false),
+    ("fo", "force", false),
+    ("bd", "bundle", true),
+    ("wes", "with-extrasources", false),
+];
+
+// global flags without a value
+const SWITCHES: &[(&str, &str)] = &[("y", "yes"), ("ni", "no-input"), ("dr", "dry-run")];
+
+enum Flag {
+    // long name and whether it takes a value
+    Global(&'static str, bool),
+    Command(&'static Legacy),
+    Modifier(&'static str, bool),
+}
+
+fn find_flag(flag: &str) -> Option<Flag> {
+    let matches = |short: &str, long: &str| flag.strip_prefix("--") == Some(long) || (!flag.starts_with("--") && flag.strip_prefix('-') == Some(short));
+
+    if matches("pf", "profile") {
+        return Some(Flag::Global("profile", true));
+    }
+    if let Some(ov) = OVERRIDES.iter().find(|ov| matches(ov.short, ov.long)) {
+        return Some(Flag::Global(ov.long, true));
+    }
+    if let Some((_, long)) = SWITCHES.iter().find(|(short, long)| matches(short, long)) {
+        return Some(Flag::Global(long, false));
+    }
+    if let Some(legacy) = COMMANDS.iter().find(|legacy| matches(legacy.short, legacy.long)) {
+        return Some(Flag::Command(legacy));
+    }
+    MODIFIERS.iter().find(|(short, long, _)| matches(short, long)).map(|(_, long, value)| Flag::Modifier(long, *value))
+}
+
+// rewrites the old flag interface, eg. `-c zlib -rb zlib`, into one subcommand argv per command, eg.
+// `pkgb checkout zlib` and `pkg build zlib`. modifiers go to every command accepting them.
+// argv already using subcommands is returned as is, apart from the short global flags like -ma
+pub fn translate(args: Vec<String>) -> Result<Vec<Vec<String>>, String> {
+    let mut args = args.into_iter();
+    let program = args.next().unwrap_or("rranch".to_owned());
+    let args = args.collect::<Vec<String>>();
+
+    let mut globals = Vec::new();
+    let mut commands: Vec<(&Legacy, Option<String>)> = Vec::new();
+    let mut modifiers: Vec<(&str, Option<String>)> = Vec::new();
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        index += 1;
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with('-') => (flag, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let found = if arg.starts_with('-') { find_flag(flag) } else { None };
+        let Some(found) = found else {
+            // the first positional starts a subcommand, everything from there on is left to clap
+            if commands.is_empty() && modifiers.is_empty() && (!arg.starts_with('-') || arg == "-h" || arg == "--help") {
+                let rest = args[index - 1..].iter().map(|arg| expand_global(arg));
+                return Ok(vec![[vec![program], globals].concat().into_iter().chain(rest).collect()]);
+            }
+            return Err(format!("unexpected argument {arg}"));
+        };
+
+        let takes_value = match found {
+            Flag::Global(_, value) => value,
+            Flag::Command(legacy) => legacy.value,
+            Flag::Modifier(_, value) => value,
+        };
+        let value = match (takes_value, inline) {
+            (false, None) => None,
+            (false, Some(_)) => return Err(format!("{flag} does not take a value")),
+            (true, Some(value)) => Some(value),
+            (true, None) => match args.get(index) {
+                Some(value) => {
+                    index += 1;
+                    Some(value.clone())
+                }
+                None => return Err(format!("{flag} needs a value")),
+            },
+        };
+
+        match found {
+            Flag::Global(long, _) => {
+                globals.push(format!("--{long}"));
+                globals.extend(value);
+            }
+            Flag::Command(legacy) => commands.push((legacy, value)),
+            Flag::Modifier(long, _) => modifiers.push((long, value)),
+        }
+    }
+
+    if commands.is_empty() {
+        return match modifiers.first() {
+            Some((long, _)) => Err(format!("--{long} needs a command to apply to")),
+            None => Ok(vec![[vec![program], globals].concat()]),
+        };
+    }
+
+    let mut argvs = commands
+        .iter()
+        .map(|(legacy, value)| [vec![program.clone()], globals.clone(), legacy.command.iter().map(|part| part.to_string()).collect(), value.iter().cloned().collect()].concat())
+        .collect::<Vec<Vec<String>>>();
+    for (long, value) in modifiers {
+        let mut applied = false;
+        for ((legacy, _), argv) in commands.iter().zip(argvs.iter_mut()) {
+            if legacy.positional.contains(&long) {
+                argv.extend(value.clone());
+            } else if accepts(legacy.command, long) {
+                argv.push(format!("--{long}"));
+                argv.extend(value.clone());
+            } else {
+                continue;
+            }
+            applied = true;
+        }
+        if !applied {
+            return Err(format!("--{long} does not apply to any of the given commands"));
+        }
+    }
+    Ok(argvs)
+}
+
+// -ma=x to --master-addr=x, anything else is kept
+fn expand_global(arg: &str) -> String {
+    let (flag, value) = match arg.split_once('=') {
+        Some((flag, value)) => (flag, Some(value)),
+        None => (arg, None),
+    };
+    match find_flag(flag) {
+        Some(Flag::Global(long, _)) if !flag.starts_with("--") => match value {
+            Some(value) => format!("--{long}={value}"),
+            None => format!("--{long}"),
+        },
+        _ => arg.to_owned(),
+    }
+}
+
+// true if the subcommand at path has a --long flag
+fn accepts(path: &[&str], long: &str) -> bool {
+    let mut command = command();
+    for part in path.iter().filter(|part| !part.starts_with('-')) {
+        command = match command.find_subcommand(part) {
+            Some(subcommand) => subcommand.clone(),
+            None => return false,
+        };
+    }
+    let accepted = command.get_arguments().any(|arg| arg.get_long() == Some(long));
+    accepted
+}
@@ -1 +1,2 @@
-pub mod argparser;
+pub mod cli;
+pub mod legacy;
@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use console::Style;
+use log::{info, warn};
+
+use crate::{
+    json::{extra_source::ExtraSourceReceive, pkgbuild::PackageBuild},
+    structs::{
+        bundle::{BundledExtraSource, BundledPkgbuild, Manifest, BUNDLE_VERSION, MANIFEST},
+        client::Client,
+        config::config_master::Master,
+        transfer::TransferSummary,
+    },
+    util::{
+        download::{download, is_same_content, sha256_of, sha256sum, Checksum},
+        funcs::{create_temp_dir, get_yn},
+    },
+};
+
+impl Client {
+    // writes every pkgbuild, and with_extrasources every extrasource, into a tar.zst at path together with a manifest
+    // of checksums. nothing is written unless every pkgbuild could be fetched
+    pub fn export_bundle(&mut self, master: &Master, path: &str, with_extrasources: bool, force: bool) -> Result<(), std::io::Error> {
+        if Path::new(path).exists() && !force && !get_yn(&format!("{path} exists, do you want to overwrite it?"), false)? {
+            info!("Aborted due to user choice");
+            return Ok(());
+        }
+
+        let mut names = self.get_managed_pkgbs()?;
+        names.sort();
+        let results = self.run_parallel("Fetching", &names, String::clone, |client, name| client.get_pkgb(name))?;
+        let mut pkgbs = Vec::new();
+        let mut failed = Vec::new();
+        for (name, result) in names.into_iter().zip(results) {
+            match result {
+                Ok(pkgb) => pkgbs.push(pkgb),
+                Err(err) => failed.push(format!("{name} ({err})")),
+            }
+        }
+        if !failed.is_empty() {
+            return Err(std::io::Error::other(format!("Failed to fetch {} pkgbuilds, no bundle was written: {}", failed.len(), failed.join(", "))));
+        }
+
+        let extra_sources = if with_extrasources { self.get_extra_sources()? } else { Vec::new() };
+        let dir = create_temp_dir("rranch-bundle")?;
+        let result = write_bundle(master, path, &pkgbs, &extra_sources, &dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        result?;
+
+        println!(
+            "{}",
+            Style::new().bold().apply_to(format!("Wrote {} pkgbuilds and {} extrasources to {path}", pkgbs.len(), extra_sources.len()))
+        );
+        Ok(())
+    }
+
+    // checks the bundle at path against its manifest, recreates its extrasources and submits its pkgbuilds with the
+    // extrasource ids of this master
+    pub fn import_bundle(&mut self, url: &str, path: &str) -> Result<(), std::io::Error> {
+        let dir = create_temp_dir("rranch-bundle")?;
+        let result = self.import_unpacked(url, path, &dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn import_unpacked(&mut self, url: &str, path: &str, dir: &Path) -> Result<(), std::io::Error> {
+        tar::Archive::new(zstd::Decoder::new(std::fs::File::open(path)?)?)
+            .unpack(dir)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path} is not a tar.zst bundle: {err}")))?;
+        let manifest = match std::fs::read_to_string(dir.join(MANIFEST)) {
+            Ok(content) => Manifest::from_str(&content)?,
+            Err(err) => return Err(std::io::Error::new(err.kind(), format!("{path} has no {MANIFEST}, it is not a bundle"))),
+        };
+        info!(
+            "Bundle of {} pkgbuilds and {} extrasources exported from {}",
+            manifest.pkgbuild.len(),
+            manifest.extrasource.len(),
+            manifest.master
+        );
+
+        // the master is only touched once the whole bundle is known to be intact
+        let files = manifest
+            .pkgbuild
+            .iter()
+            .map(|pkgb| (&pkgb.file, &pkgb.sha256))
+            .chain(manifest.extrasource.iter().map(|extra_source| (&extra_source.file, &extra_source.sha256)));
+        for (file, sha256) in files {
+            let actual = sha256sum(&get_bundled_path(dir, file)?.display().to_string())?;
+            if &actual != sha256 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Checksum mismatch for {file} in {path}: expected {sha256}, got {actual}"),
+                ));
+            }
+        }
+
+        let ids = self.import_extra_sources(url, dir, &manifest.extrasource)?;
+        let known = self.get_extra_sources()?;
+
+        let mut summary = TransferSummary::default();
+        let mut pkgbs = Vec::new();
+        for bundled in &manifest.pkgbuild {
+            match std::fs::read_to_string(dir.join(&bundled.file)).and_then(|content| PackageBuild::from_str(&content)) {
+                Ok(mut pkgb) => {
+                    pkgb.extra_sources = pkgb.extra_sources.into_iter().map(|id| ids.get(&id).cloned().unwrap_or(id)).collect();
+                    for id in pkgb.extra_sources.iter().filter(|id| !ids.values().any(|new| new == *id) && !known.iter().any(|extra_source| &extra_source.id == *id)) {
+                        warn!("Extrasource {id} of {} is neither in the bundle nor on the master", pkgb.name);
+                    }
+                    pkgbs.push(pkgb);
+                }
+                Err(err) => summary.failed.push((bundled.name.clone(), err.to_string())),
+            }
+        }
+        self.import_pkgbs(pkgbs, summary)
+    }
+
+    // uploads the bundled extrasources this master does not have yet, returns their old ids mapped to the ids here.
+    // one with the same filename and checksum is reused instead of uploaded again
+    fn import_extra_sources(&mut self, url: &str, dir: &Path, bundled: &[BundledExtraSource]) -> Result<HashMap<String, String>, std::io::Error> {
+        let mut ids = HashMap::new();
+        if bundled.is_empty() {
+            return Ok(ids);
+        }
+
+        let mut existing = self.get_extra_sources()?;
+        for extra_source in bundled {
+            // the master publishes no checksums of extrasources, so candidates with the same name are compared by content
+            let size = std::fs::metadata(dir.join(&extra_source.file))?.len();
+            let mut present = None;
+            for candidate in existing.iter().filter(|candidate| candidate.filename == extra_source.filename) {
+                if is_same_content(&format!("{}?get=extrasource&esid={}", url, candidate.id), size, &extra_source.sha256)? {
+                    present = Some(candidate.id.clone());
+                    break;
+                }
+            }
+            if let Some(id) = present {
+                info!("Skipping {}, already present on the master as {id}", extra_source.filename);
+                ids.insert(extra_source.id.clone(), id);
+                continue;
+            }
+
+            println!("Uploading {}...", extra_source.filename);
+            self.transfer_extra_source(&dir.join(&extra_source.file).display().to_string(), &extra_source.description)?;
+            if self.dry_run {
+                warn!("Dry run, {} keeps its old id {}", extra_source.filename, extra_source.id);
+                ids.insert(extra_source.id.clone(), extra_source.id.clone());
+                continue;
+            }
+
+            // the master does not report the id of a new extrasource, so diff the listing instead
+            let current = self.get_extra_sources()?;
+            let new = current
+                .iter()
+                .filter(|candidate| candidate.filename == extra_source.filename && !existing.iter().any(|old| old.id == candidate.id))
+                .collect::<Vec<&ExtraSourceReceive>>();
+            let [new] = new.as_slice() else {
+                return Err(std::io::Error::other(format!("Could not tell the new id of {} on the master, no pkgbuild was submitted", extra_source.filename)));
+            };
+            println!("{} {} -> {}", Style::new().bold().apply_to(&extra_source.filename), extra_source.id, new.id);
+            ids.insert(extra_source.id.clone(), new.id.clone());
+            existing = current;
+        }
+        Ok(ids)
+    }
+}
+
+// downloads the extrasources into dir first, the manifest leads the archive and needs their checksums.
+// the archive is written to <path>.part and renamed once complete
+fn write_bundle(master: &Master, path: &str, pkgbs: &[PackageBuild], extra_sources: &[ExtraSourceReceive], dir: &Path) -> Result<(), std::io::Error> {
+    let url = master.get_fetch_url();
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut manifest = Manifest {
+        version: BUNDLE_VERSION,
+        master: format!("{}:{}", master.get_addr(), master.get_port()),
+        fetch_url: url.clone(),
+        created,
+        ..Default::default()
+    };
+
+    let mut contents = Vec::new();
+    for pkgb in pkgbs {
+        let content = pkgb.to_vec()?.join("\n");
+        let file = format!("pkgbuilds/{}.bpb", pkgb.name);
+        manifest.pkgbuild.push(BundledPkgbuild {
+            name: pkgb.name.clone(),
+            file: file.clone(),
+            sha256: sha256_of(content.as_bytes()),
+        });
+        contents.push((file, content));
+    }
+
+    let mut downloads = Vec::new();
+    for extra_source in extra_sources {
+        println!("Downloading {} ({})...", extra_source.filename, extra_source.id);
+        let local = dir.join(&extra_source.id).display().to_string();
+        // the master publishes no checksum to verify against, the manifest records the one of the download
+        download(&format!("{}?get=extrasource&esid={}", url, extra_source.id), &local, Checksum::Skip)?;
+        let file = format!("extrasources/{}/{}", extra_source.id, extra_source.filename);
+        manifest.extrasource.push(BundledExtraSource {
+            id: extra_source.id.clone(),
+            filename: extra_source.filename.clone(),
+            description: extra_source.description.clone(),
+            file: file.clone(),
+            sha256: sha256sum(&local)?,
+        });
+        downloads.push((file, local));
+    }
+
+    let part = format!("{path}.part");
+    let mut builder = tar::Builder::new(zstd::Encoder::new(std::fs::File::create(&part)?, 0)?);
+    append(&mut builder, MANIFEST, manifest.to_string()?.as_bytes(), created)?;
+    for (file, content) in &contents {
+        append(&mut builder, file, content.as_bytes(), created)?;
+    }
+    for (file, local) in &downloads {
+        builder.append_path_with_name(local, file)?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    std::fs::rename(part, path)
+}
+
+fn append<W: Write>(builder: &mut tar::Builder<W>, name: &str, content: &[u8], mtime: u64) -> Result<(), std::io::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)
+}
+
+// paths in the manifest must stay inside the unpacked bundle
+fn get_bundled_path(dir: &Path, file: &str) -> Result<PathBuf, std::io::Error> {
+    if !Path::new(file).components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Bundle manifest lists {file} outside of the bundle")));
+    }
+    Ok(dir.join(file))
+}
@@ -15,28 +15,38 @@ use crate::{
         solution::Solution,
     },
     structs::{client::Client, deps::Deps, diff::Diff},
-    util::funcs::{get_input, get_yn, print_cols},
+    util::{
+        download::{is_same_content, sha256sum},
+        funcs::{get_extra_source_files, get_input_default, get_yn, print_cols, truncate_to},
+    },
 };
 
 impl Client {
     pub fn auth(&mut self, machine_idenifier: &str, machine_type: &str, machine_authkey: &str, version: u16) -> Result<AuthResponse, std::io::Error> {
-        let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new(
-            "AUTH",
-            Some(serde_json::to_value(AuthRequest::new(machine_idenifier, machine_type, machine_authkey, version))?),
-        ))?)?)?;
+        let login = AuthRequest::new(machine_idenifier, machine_type, machine_authkey, version);
+        let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("AUTH", Some(serde_json::to_value(&login)?)))?)?)?;
 
         match resp.statuscode {
-            StatusCode::Ok => Ok(serde_json::from_value::<AuthResponse>(resp.payload)?),
+            StatusCode::Ok => {
+                self.login = Some(login);
+                Ok(serde_json::from_value::<AuthResponse>(resp.payload)?)
+            }
             StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, serde_json::to_string(&resp.payload)?)),
         }
     }
 
-    pub fn checkout(&mut self, pkgname: &str) -> Result<(), std::io::Error> {
-        self.get_pkgb(pkgname)?.create_workdir()
+    pub fn checkout(&mut self, pkgname: &str, force: bool) -> Result<(), std::io::Error> {
+        self.get_pkgb(pkgname)?.create_workdir(force)
     }
 
     pub fn submit(&mut self, path: &str) -> Result<(), std::io::Error> {
         let pkgb = PackageBuild::from_str(&std::fs::read_to_string(path)?)?;
+        self.preview_pkgb(&pkgb, path)?;
+        self.submit_pkgb(pkgb).map(|_| ())
+    }
+
+    // returns false if the user aborted the submit
+    pub fn submit_pkgb(&mut self, pkgb: PackageBuild) -> Result<bool, std::io::Error> {
         let combined = self.get_all()?;
 
         let mut warn = false;
@@ -62,21 +72,26 @@ impl Client {
             }
         });
 
-        if warn {
-            if !get_yn("Missing dependencies were found, do you want to submit anyways?", false)? {
-                info!("Aborted due to user choice");
-                return Ok(());
-            }
+        if warn && !get_yn("Missing dependencies were found, do you want to submit anyways?", false)? {
+            info!("Aborted due to user choice");
+            return Ok(false);
         }
 
+        println!("{}", self.send_pkgb(&pkgb)?);
+        Ok(true)
+    }
+
+    // submits without checking dependencies, returns the master's answer
+    pub fn send_pkgb(&mut self, pkgb: &PackageBuild) -> Result<String, std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("SUBMIT", Some(serde_json::to_value(pkgb)?)))?)?)?;
 
         match resp.statuscode {
-            StatusCode::Ok => Ok(println!("{}", serde_json::to_string(&resp.payload)?)),
+            StatusCode::Ok => Ok(serde_json::to_string(&resp.payload)?),
             StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, serde_json::to_string(&resp.payload)?)),
         }
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn build(&mut self, pkgname: &str, release: bool) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("BUILD", Some(serde_json::to_value(Build::new(pkgname, release))?)))?)?)?;
 
@@ -86,6 +101,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::unit_arg)]
     pub fn show_sys_log(&mut self) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETSYSLOG", None))?)?)?;
 
@@ -183,6 +199,7 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn rebuild_dependers(&mut self, pkgname: &str) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("REBUILDDEPENDERS", Some(serde_json::to_value(pkgname)?)))?)?)?;
 
@@ -201,6 +218,7 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn show_clients(&mut self) -> Result<(), std::io::Error> {
         let bold = Style::new().bold();
 
@@ -261,6 +279,7 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn clear_completed(&mut self) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("CLEARCOMPLETEDJOBS", None))?)?)?;
 
@@ -270,6 +289,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn cancel_queued(&mut self, job_id: Option<&str>) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&if job_id.is_some() {
             Request::new("CANCELQUEUEDJOB", Some(serde_json::to_value(job_id.unwrap_or_default())?))
@@ -283,6 +303,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn submit_solution(&mut self, path: &str, release: bool) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(
             &self.write_read(&serde_json::to_string(&Request::new(
@@ -303,6 +324,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn show_client_info(&mut self, clientname: &str) -> Result<(), std::io::Error> {
         let bold = Style::new().bold();
 
@@ -314,6 +336,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn remove_pkg(&mut self, pkgname: &str) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("DELETEPKG", Some(serde_json::to_value(pkgname)?)))?)?)?;
 
@@ -326,21 +349,78 @@ impl Client {
     pub fn show_extra_sources(&mut self) -> Result<(), std::io::Error> {
         let bold = Style::new().bold();
         let italic = Style::new().italic();
+        let yellow = Style::new().yellow();
+
+        let extra_sources = self.get_extra_sources()?;
+        let usage = self.get_extra_source_usage()?;
+
+        println!("{}", bold.apply_to("Managed Extra Sources"));
+        println!("{}", italic.apply_to(format!("{:<40} {:<35} {:<30} {}", "ID", "File", "Description", "Used by")));
+        extra_sources.iter().for_each(|extra_source| {
+            let used_by = match usage.get(&extra_source.id) {
+                Some(pkgs) => pkgs.join(", "),
+                None => yellow.apply_to("unused").to_string(),
+            };
+            println!(
+                "{:<40} {:<35} {:<30} {}",
+                extra_source.id,
+                extra_source.filename,
+                truncate_to(extra_source.description.clone(), 28),
+                used_by
+            )
+        });
+        Ok(())
+    }
 
-        let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETMANAGEDEXTRASOURCES", None))?)?)?;
+    pub fn show_unused_extra_sources(&mut self) -> Result<(), std::io::Error> {
+        let bold = Style::new().bold();
+        let italic = Style::new().italic();
 
-        match resp.statuscode {
-            StatusCode::Ok => {
-                println!("{}", bold.apply_to("Managed Extra Sources"));
-                println!("{}", italic.apply_to(format!("{:<40} {:<35} {}", "ID", "File", "Description")));
-                Ok(serde_json::from_value::<Vec<ExtraSourceReceive>>(resp.payload)?
-                    .iter()
-                    .for_each(|extra_source| println!("{extra_source}")))
-            }
-            StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::new(std::io::ErrorKind::Other, serde_json::to_string(&resp.payload)?)),
+        let extra_sources = self.get_extra_sources()?;
+        let usage = self.get_extra_source_usage()?;
+
+        println!("{}", bold.apply_to("Unused Extra Sources"));
+        println!("{}", italic.apply_to(format!("{:<40} {:<35} {}", "ID", "File", "Description")));
+        extra_sources
+            .iter()
+            .filter(|extra_source| !usage.contains_key(&extra_source.id))
+            .for_each(|extra_source| println!("{extra_source}"));
+
+        let mut dangling = usage
+            .iter()
+            .filter(|(es_id, _)| !extra_sources.iter().any(|extra_source| &extra_source.id == *es_id))
+            .collect::<Vec<(&String, &Vec<String>)>>();
+        dangling.sort();
+
+        println!("{}", bold.apply_to("Dangling References"));
+        println!("{}", italic.apply_to(format!("{:<40} {}", "ID", "Referenced by")));
+        dangling.iter().for_each(|(es_id, pkgs)| println!("{:<40} {}", es_id, pkgs.join(", ")));
+        Ok(())
+    }
+
+    pub fn prune_extra_sources(&mut self) -> Result<(), std::io::Error> {
+        let extra_sources = self.get_extra_sources()?;
+        let usage = self.get_extra_source_usage()?;
+
+        let unused = extra_sources.iter().filter(|extra_source| !usage.contains_key(&extra_source.id)).collect::<Vec<&ExtraSourceReceive>>();
+        if unused.is_empty() {
+            info!("No unused extrasources found");
+            return Ok(());
         }
+
+        unused.iter().for_each(|extra_source| println!("{extra_source}"));
+        if !get_yn(&format!("Do you want to remove {} unused extrasources?", unused.len()), false)? {
+            info!("Aborted due to user choice");
+            return Ok(());
+        }
+
+        for extra_source in unused {
+            self.remove_extra_source(&extra_source.id)?;
+        }
+        Ok(())
     }
 
+    #[allow(clippy::io_other_error, clippy::unit_arg)]
     pub fn remove_extra_source(&mut self, es_id: &str) -> Result<(), std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("REMOVEEXTRASOURCE", Some(serde_json::to_value(es_id)?)))?)?)?;
 
@@ -350,16 +430,29 @@ impl Client {
         }
     }
 
-    pub fn submit_extra_source(&mut self, path: &str) -> Result<(), std::io::Error> {
-        print!("Description for {path}: ");
+    pub fn submit_extra_source(&mut self, path: &str, description: Option<&str>) -> Result<(), std::io::Error> {
+        let description = match description {
+            Some(description) => description.to_owned(),
+            None => get_input_default(&format!("Description for {path}"), "")?,
+        };
+        println!("{}", self.transfer_extra_source(path, &description)?);
+        Ok(())
+    }
+
+    // runs the TRANSFEREXTRASOURCE / raw bytes / COMPLETETRANSFER sequence, returns the final payload
+    pub fn transfer_extra_source(&mut self, path: &str, description: &str) -> Result<String, std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new(
             "TRANSFEREXTRASOURCE",
-            Some(serde_json::to_value(ExtraSourceSubmit::new(path, get_input()?.as_str())?)?),
+            Some(serde_json::to_value(ExtraSourceSubmit::new(path, description)?)?),
         ))?)?)?;
 
         match resp.statuscode {
             StatusCode::Ok => debug!("{}", serde_json::to_string(&resp.payload)?),
-            StatusCode::InternalServerError | StatusCode::RequestFailure => return Err(std::io::Error::new(std::io::ErrorKind::Other, serde_json::to_string(&resp.payload)?)),
+            StatusCode::InternalServerError | StatusCode::RequestFailure => return Err(std::io::Error::other(serde_json::to_string(&resp.payload)?)),
+        }
+        // the master never saw the request, so there is no transfer to complete
+        if self.dry_run {
+            return Ok(serde_json::to_string(&resp.payload)?);
         }
 
         self.write_raw(std::fs::read(Path::new(path))?)?;
@@ -368,15 +461,65 @@ impl Client {
 
         match resp.statuscode {
             StatusCode::Ok => info!("{}", serde_json::to_string(&resp.payload)?),
-            StatusCode::InternalServerError | StatusCode::RequestFailure => return Err(std::io::Error::new(std::io::ErrorKind::Other, serde_json::to_string(&resp.payload)?)),
+            StatusCode::InternalServerError | StatusCode::RequestFailure => return Err(std::io::Error::other(serde_json::to_string(&resp.payload)?)),
         }
 
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("COMPLETETRANSFER", None))?)?)?;
 
         match resp.statuscode {
-            StatusCode::Ok => Ok(println!("{}", serde_json::to_string(&resp.payload)?)),
-            StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::new(std::io::ErrorKind::Other, serde_json::to_string(&resp.payload)?)),
+            StatusCode::Ok => Ok(serde_json::to_string(&resp.payload)?),
+            StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::other(serde_json::to_string(&resp.payload)?)),
+        }
+    }
+
+    pub fn submit_extra_sources(&mut self, url: &str, path: &str) -> Result<(), std::io::Error> {
+        let bold = Style::new().bold();
+
+        let files = get_extra_source_files(path)?;
+        let existing = self.get_extra_sources()?;
+
+        let mut uploaded = Vec::new();
+        for (file, description) in files {
+            let filename = Path::new(&file).file_name().unwrap_or_default().to_str().unwrap_or_default().to_owned();
+            let (size, checksum) = (std::fs::metadata(&file)?.len(), sha256sum(&file)?);
+
+            // the master publishes no checksums of extrasources, so candidates with the same name are compared by content
+            let mut present = false;
+            for extra_source in existing.iter().filter(|extra_source| extra_source.filename == filename) {
+                if is_same_content(&format!("{}?get=extrasource&esid={}", url, extra_source.id), size, &checksum)? {
+                    present = true;
+                    break;
+                }
+            }
+            if present {
+                info!("Skipping {filename}, already present on the master");
+                continue;
+            }
+
+            println!("Uploading {filename}...");
+            self.transfer_extra_source(&file, &description)?;
+            uploaded.push(filename);
+        }
+
+        if uploaded.is_empty() {
+            info!("Nothing to upload");
+            return Ok(());
         }
+
+        // the master does not report the id of a new extrasource, so diff the listing instead
+        let new = self
+            .get_extra_sources()?
+            .into_iter()
+            .filter(|extra_source| !existing.iter().any(|old| old.id == extra_source.id) && uploaded.contains(&extra_source.filename))
+            .collect::<Vec<ExtraSourceReceive>>();
+
+        println!("{}", bold.apply_to("Uploaded Extra Sources"));
+        new.iter().for_each(|extra_source| println!("{extra_source}"));
+        println!(
+            "extra_sources={}",
+            new.iter().map(|extra_source| format!("[{}]", extra_source.id)).collect::<Vec<String>>().join("")
+        );
+        Ok(())
     }
 
     pub fn show_diff(&mut self) -> Result<(), std::io::Error> {
@@ -1,24 +1,52 @@
-use std::{collections::HashMap, io::Write, process::Command, time::Duration};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Component, Path},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
 
-use console::{Style, Term};
-use curl::easy::{Easy, WriteError};
-use indicatif::{ProgressBar, ProgressStyle};
-use log::{error, info, trace};
+use console::Style;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar};
+use log::{info, trace, warn};
 
 use crate::{
     json::{
         dependers::Dependers,
+        extra_source::ExtraSourceReceive,
         job_request::JobRequest,
         jobs_status::{Job, JobsStatus},
         pkgbuild::PackageBuild,
         request::Request,
         response::{Response, StatusCode},
     },
-    structs::{client::Client, diff::Diff},
-    util::funcs::{get_input, get_pkgbs, get_yn, print_cols},
+    structs::{
+        client::Client,
+        config::{config_bump::RealVersionPolicy, config_cache::Cache, config_master::Master},
+        diff::Diff,
+        lockfile::{LockedPackage, Lockfile},
+        template::{NewOptions, Template},
+        transfer::TransferSummary,
+    },
+    util::{
+        cache,
+        download::{count_progress_style, download, download_with, fetch_validator, multi_progress_style, progress_style, sha256sum, worker_progress_style, Checksum},
+        funcs::{check_editable, create_temp_dir, format_mode, get_input, get_input_default, get_pkgbs, get_yn, is_interactive, print_cols, print_diff},
+        shell::{interruptible, sleep_interruptible},
+        source::{guess_template, guess_version, replace_version},
+    },
 };
 
+// number of parallel downloads when fetching a dependency closure
+const FETCH_WORKERS: usize = 4;
+// number of connections export and import use at once
+const TRANSFER_WORKERS: usize = 4;
+
 impl Client {
+    #[allow(clippy::io_other_error)]
     pub fn show_latest_complete_log(&mut self) -> Result<(), std::io::Error> {
         let req = Request::new("GETJOBSTATUS", None);
 
@@ -30,6 +58,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn show_latest_running_log(&mut self) -> Result<(), std::io::Error> {
         let req = Request::new("GETJOBSTATUS", None);
 
@@ -43,10 +72,12 @@ impl Client {
 
     pub fn watch_jobs(&mut self, interval: &str) -> Result<(), std::io::Error> {
         let n = interval.parse::<u64>().unwrap_or(1);
-        loop {
+        interruptible(|| loop {
             self.show_jobs_status(true)?;
-            std::thread::sleep(Duration::from_secs(n));
-        }
+            if sleep_interruptible(Duration::from_secs(n)) {
+                return Ok(());
+            }
+        })
     }
 
     pub fn get_dependecies(&mut self, pkgname: &str) -> Result<(Vec<String>, Vec<String>), std::io::Error> {
@@ -86,6 +117,7 @@ impl Client {
             .collect::<Vec<Diff>>())
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn get_managed_pkgs(&mut self) -> Result<Vec<String>, std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETMANAGEDPKGS", None))?)?)?;
 
@@ -95,6 +127,7 @@ impl Client {
         }
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn get_managed_pkgbs(&mut self) -> Result<Vec<String>, std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETMANAGEDPKGBUILDS", None))?)?)?;
 
@@ -114,8 +147,9 @@ impl Client {
     }
 
     pub fn edit(&mut self, pkgname: &str, editor: &str) -> Result<(), std::io::Error> {
-        self.checkout(pkgname)?;
         let path = format!("{}/package.bpb", pkgname);
+        check_editable(&path)?;
+        self.checkout(pkgname, false)?;
         self.edit_local(&path, editor)?;
         if get_yn("Do you want to delete the local packagebuild", true)? {
             std::fs::remove_dir_all(pkgname)?;
@@ -123,7 +157,9 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn edit_local(&mut self, path: &str, editor: &str) -> Result<(), std::io::Error> {
+        check_editable(path)?;
         let child = Command::new(editor).arg(path).spawn();
 
         match child {
@@ -137,8 +173,10 @@ impl Client {
             }
         }
 
+        let pkgb = PackageBuild::from_str(&std::fs::read_to_string(path)?)?;
+        self.preview_pkgb(&pkgb, path)?;
         if get_yn("Do you want to submit the changes?", false)? {
-            self.submit(path)?;
+            self.submit_pkgb(pkgb)?;
         } else {
             info!("Aborted submit due to user choice.");
         }
@@ -146,86 +184,499 @@ impl Client {
         Ok(())
     }
 
-    pub fn export(&mut self) -> Result<(), std::io::Error> {
-        let mut pkgbs = self.get_managed_pkgbs()?;
-        pkgbs.sort();
-        if !get_yn(&format!("Do you want to fetch {} pkgbuilds?", pkgbs.len()), false)? {
+    pub fn diff_pkgb(&mut self, path: &str) -> Result<(), std::io::Error> {
+        let pkgb = PackageBuild::from_str(&std::fs::read_to_string(path)?)?;
+        if !self.preview_pkgb(&pkgb, path)? {
+            info!("{path} does not differ from the master's {}", pkgb.name);
+        }
+        Ok(())
+    }
+
+    // prints the changes of pkgb against the master's copy, followed by a summary of the list changes.
+    // returns false if there are none
+    pub fn preview_pkgb(&mut self, pkgb: &PackageBuild, path: &str) -> Result<bool, std::io::Error> {
+        let bold = Style::new().bold();
+        let red = Style::new().red();
+        let green = Style::new().green();
+
+        let (old, old_text) = if self.get_managed_pkgbs()?.contains(&pkgb.name) {
+            let old = self.get_pkgb(&pkgb.name)?;
+            let text = old.to_vec()?.join("\n");
+            (old, text)
+        } else {
+            info!("{} does not exist on the master yet", pkgb.name);
+            (PackageBuild::default(), String::new())
+        };
+
+        // both sides go through to_vec so formatting differences of the local file do not show up
+        if !print_diff(&old_text, &pkgb.to_vec()?.join("\n"), &format!("master/{}", pkgb.name), path) {
+            return Ok(false);
+        }
+
+        let changes = old.get_list_changes(pkgb);
+        if !changes.is_empty() {
+            println!("{}", bold.apply_to("Dependency changes"));
+            for change in changes {
+                let style = if change.starts_with('+') { &green } else { &red };
+                println!("  {}", style.apply_to(change));
+            }
+        }
+        Ok(true)
+    }
+
+    // writes the master's pkgbuilds to ./<name>/package.bpb over several connections. new ones are written right away,
+    // local ones differing from the master are only overwritten with force or after asking
+    pub fn export(&mut self, force: bool) -> Result<(), std::io::Error> {
+        let mut names = self.get_managed_pkgbs()?;
+        names.sort();
+        if !get_yn(&format!("Do you want to fetch {} pkgbuilds?", names.len()), false)? {
             info!("Aborted due to user choice");
             return Ok(());
         }
-        let progress = ProgressBar::new(pkgbs.len() as u64);
-        let term = Term::stdout();
-        for pkgb in pkgbs {
-            term.clear_screen()?;
-            println!("Downloading {pkgb}...");
-            progress.inc(1);
-            self.checkout(&pkgb)?;
+
+        let results = self.run_parallel("Comparing", &names, String::clone, |client, name| {
+            let pkgb = client.get_pkgb(name)?;
+            let local = match std::fs::read_to_string(format!("{name}/package.bpb")) {
+                Ok(content) => Some(PackageBuild::from_str(&content)?),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            };
+            Ok((pkgb, local))
+        })?;
+
+        let mut summary = TransferSummary::default();
+        let mut changed = Vec::new();
+        for (name, result) in names.into_iter().zip(results) {
+            match result {
+                Ok((pkgb, None)) => match pkgb.write_workdir() {
+                    Ok(()) => summary.added.push(name),
+                    Err(err) => summary.failed.push((name, err.to_string())),
+                },
+                Ok((pkgb, Some(local))) => match pkgb.is_same(&local) {
+                    Ok(true) => summary.unchanged.push(name),
+                    Ok(false) => changed.push(pkgb),
+                    Err(err) => summary.failed.push((name, err.to_string())),
+                },
+                Err(err) => summary.failed.push((name, err.to_string())),
+            }
         }
 
-        progress.finish();
-        term.clear_screen()?;
-        Ok(())
+        let overwrite = !changed.is_empty()
+            && (force
+                || get_yn(
+                    &format!("{} local pkgbuilds differ from the master ({}), overwrite them?", changed.len(), changed.iter().map(|pkgb| pkgb.name.as_str()).collect::<Vec<&str>>().join(", ")),
+                    false,
+                )?);
+        for pkgb in changed {
+            if !overwrite {
+                summary.skipped.push(pkgb.name);
+                continue;
+            }
+            match pkgb.write_workdir() {
+                Ok(()) => summary.updated.push(pkgb.name),
+                Err(err) => summary.failed.push((pkgb.name, err.to_string())),
+            }
+        }
+
+        summary.print();
+        summary.check("export")
     }
 
+    // submits the pkgbuilds under path that are new or differ from the master, over several connections.
+    // failures are collected instead of stopping the import
     pub fn import(&mut self, path: &str) -> Result<(), std::io::Error> {
-        let pkgbs = get_pkgbs(path)?;
+        let mut summary = TransferSummary::default();
+        let mut pkgbs = Vec::new();
+        for file in get_pkgbs(path)? {
+            match std::fs::read_to_string(&file).and_then(|content| PackageBuild::from_str(&content)) {
+                Ok(pkgb) => pkgbs.push(pkgb),
+                Err(err) => summary.failed.push((file, err.to_string())),
+            }
+        }
+        self.import_pkgbs(pkgbs, summary)
+    }
+
+    // submits the pkgbuilds that are new or differ from the master, summary may already hold failures
+    pub fn import_pkgbs(&mut self, mut pkgbs: Vec<PackageBuild>, mut summary: TransferSummary) -> Result<(), std::io::Error> {
+        pkgbs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let managed = self.get_managed_pkgbs()?;
+        let results = self.run_parallel("Comparing", &pkgbs, |pkgb| pkgb.name.clone(), |client, pkgb| match managed.contains(&pkgb.name) {
+            true => client.get_pkgb(&pkgb.name).and_then(|remote| remote.is_same(pkgb)).map(Some),
+            false => Ok(None),
+        })?;
+
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+        for (pkgb, result) in pkgbs.into_iter().zip(results) {
+            match result {
+                Ok(None) => added.push(pkgb),
+                Ok(Some(false)) => updated.push(pkgb),
+                Ok(Some(true)) => summary.unchanged.push(pkgb.name),
+                Err(err) => summary.failed.push((pkgb.name, err.to_string())),
+            }
+        }
+        if added.is_empty() && updated.is_empty() {
+            summary.print();
+            return summary.check("import");
+        }
 
-        if !get_yn(&format!("Do you want to submit {} pkgbuilds?", pkgbs.len()), false)? {
+        // dependencies may also come with the import
+        let mut known = self.get_all()?;
+        known.extend(added.iter().map(|pkgb| pkgb.name.clone()));
+        let mut missing = false;
+        for pkgb in added.iter().chain(&updated) {
+            for (kind, deps) in [("Runtime", &pkgb.dependencies), ("Build", &pkgb.build_dependencies), ("Cross", &pkgb.cross_dependencies)] {
+                for dep in deps.iter().filter(|dep| !known.contains(dep)) {
+                    warn!("{kind} Dependency {dep} of {} does not exist!", pkgb.name);
+                    missing = true;
+                }
+            }
+        }
+        if missing && !get_yn("Missing dependencies were found, do you want to submit anyways?", false)? {
             info!("Aborted due to user choice");
             return Ok(());
         }
-        let progress = ProgressBar::new(pkgbs.len() as u64);
-        let term = Term::stdout();
-        for pkgb in pkgbs {
-            term.clear_screen()?;
-            progress.inc(1);
-            self.submit(&pkgb)?;
+        if !get_yn(&format!("Do you want to submit {} new and {} changed pkgbuilds?", added.len(), updated.len()), false)? {
+            info!("Aborted due to user choice");
+            return Ok(());
         }
 
-        term.clear_screen()?;
-        progress.finish();
-        Ok(())
+        let count = added.len();
+        let changed = [added, updated].concat();
+        let results = self.run_parallel("Submitting", &changed, |pkgb| pkgb.name.clone(), |client, pkgb| client.send_pkgb(pkgb))?;
+        for (index, (pkgb, result)) in changed.into_iter().zip(results).enumerate() {
+            match result {
+                Ok(_) if index < count => summary.added.push(pkgb.name),
+                Ok(_) => summary.updated.push(pkgb.name),
+                Err(err) => summary.failed.push((pkgb.name, err.to_string())),
+            }
+        }
+
+        summary.print();
+        summary.check("import")
     }
 
-    pub fn get_pkg(&mut self, url: &str, pkgname: &str) -> Result<(), std::io::Error> {
-        trace!("Trying to fetch from: {url}");
-        let url = format!("{}?get=package&pkgname={}", url, pkgname);
-        let mut easy = Easy::new();
+    // runs work for every item on up to TRANSFER_WORKERS connections of their own, the results are in item order.
+    // label names an item on the progress bars
+    pub fn run_parallel<T: Sync, R: Send>(
+        &self,
+        title: &str,
+        items: &[T],
+        label: impl Fn(&T) -> String + Sync,
+        work: impl Fn(&mut Client, &T) -> Result<R, std::io::Error> + Sync,
+    ) -> Result<Vec<Result<R, std::io::Error>>, std::io::Error> {
+        let mut clients = (0..TRANSFER_WORKERS.min(items.len())).map(|_| self.connect_another()).collect::<Result<Vec<Client>, std::io::Error>>()?;
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<Option<Result<R, std::io::Error>>>>());
+        let multi = MultiProgress::new();
+        let total = multi.add(ProgressBar::new(items.len() as u64));
+        total.set_style(count_progress_style()?);
+        total.set_prefix(title.to_owned());
+        let style = worker_progress_style()?;
 
-        easy.url(&url)?;
-        easy.progress(true)?;
+        std::thread::scope(|scope| {
+            for client in clients.iter_mut() {
+                let pb = multi.add(ProgressBar::new_spinner());
+                pb.set_style(style.clone());
+                pb.enable_steady_tick(Duration::from_millis(100));
+                let (next, results, total, label, work) = (&next, &results, &total, &label, &work);
+                scope.spawn(move || {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        pb.set_message(label(item));
+                        let result = work(client, item);
+                        results.lock().unwrap_or_else(|err| err.into_inner())[index] = Some(result);
+                        total.inc(1);
+                    }
+                    pb.finish_and_clear();
+                });
+            }
+        });
+        total.finish();
+        clients.iter_mut().for_each(|client| {
+            let _ = client.shutdown();
+        });
+
+        Ok(results
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner())
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(std::io::Error::other("Not processed"))))
+            .collect())
+    }
+
+    pub fn get_pkg(&mut self, master: &Master, cache: &Cache, pkgname: &str, output: Option<&str>, verify: bool) -> Result<(), std::io::Error> {
+        trace!("Trying to fetch from: {}", master.get_fetch_url());
+        let dir = output.unwrap_or(".");
+        std::fs::create_dir_all(dir)?;
+
+        // the pkgbuild provides the version the cache is keyed by, packages without one are not cached.
+        // verifying needs it as well
+        let pkgb = match self.get_pkgb(pkgname) {
+            Ok(pkgb) => Some(pkgb),
+            Err(err) if verify => return Err(err),
+            Err(err) => {
+                warn!("Failed to fetch pkgbuild for {pkgname}, bypassing cache: {err}");
+                None
+            }
+        };
 
         let pb = ProgressBar::new(1);
+        pb.set_style(progress_style()?);
+        let path = fetch_package(master, cache, pkgname, pkgb.as_ref(), dir, verify, &pb)?;
+        info!("Fetched {pkgname} to {path}");
+        Ok(())
+    }
+
+    // fetches a package and its runtime dependency closure in parallel and records them in <output>/rranch.lock
+    pub fn get_pkg_with_deps(&mut self, master: &Master, cache: &Cache, pkgname: &str, output: Option<&str>, verify: bool) -> Result<(), std::io::Error> {
+        let dir = output.unwrap_or(".");
+        std::fs::create_dir_all(dir)?;
+
+        let managed = self.get_managed_pkgs()?;
+        let (pkgbs, skipped): (Vec<PackageBuild>, Vec<PackageBuild>) = self.get_runtime_closure(pkgname)?.into_iter().partition(|pkgb| managed.contains(&pkgb.name));
+        skipped.iter().for_each(|pkgb| warn!("Skipping {}, no built package available", pkgb.name));
+
+        println!("Fetching {} packages...", pkgbs.len());
+        let queue = Mutex::new(pkgbs);
+        let locked = Mutex::new(Vec::new());
+        let failed = Mutex::new(Vec::new());
+        let multi = MultiProgress::new();
+        let style = multi_progress_style()?;
 
-        pb.set_style(
-            match ProgressStyle::with_template("{percent:>3}% [{bar:.green/white}] {bytes:>7}/{total_bytes:>7} ({bytes_per_sec})") {
-                Ok(pstyle) => pstyle,
-                Err(err) => {
-                    return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed setting progress style: {err}")));
+        std::thread::scope(|scope| {
+            for _ in 0..FETCH_WORKERS {
+                scope.spawn(|| loop {
+                    let pkgb = match queue.lock().map(|mut queue| queue.pop()) {
+                        Ok(Some(pkgb)) => pkgb,
+                        _ => break,
+                    };
+                    let pb = multi.add(ProgressBar::new(1));
+                    pb.set_style(style.clone());
+                    pb.set_prefix(pkgb.name.clone());
+
+                    let result = fetch_package(master, cache, &pkgb.name, Some(&pkgb), dir, verify, &pb).and_then(|path| sha256sum(&path));
+
+                    match result {
+                        Ok(sha256) => locked.lock().unwrap_or_else(|err| err.into_inner()).push(LockedPackage {
+                            name: pkgb.name.clone(),
+                            version: pkgb.version.clone(),
+                            real_version: pkgb.real_version.clone(),
+                            file: format!("{}.tar.xz", pkgb.name),
+                            sha256,
+                        }),
+                        Err(err) => failed.lock().unwrap_or_else(|err| err.into_inner()).push(format!("{}: {err}", pkgb.name)),
+                    }
+                });
+            }
+        });
+
+        let mut lockfile = Lockfile::new(&master.get_fetch_url());
+        lockfile.package = locked.into_inner().unwrap_or_else(|err| err.into_inner());
+        lockfile.package.sort_by(|a, b| a.name.cmp(&b.name));
+        lockfile.write(&format!("{dir}/rranch.lock"))?;
+        info!("Fetched {} packages to {dir}", lockfile.package.len());
+
+        let failed = failed.into_inner().unwrap_or_else(|err| err.into_inner());
+        if !failed.is_empty() {
+            return Err(std::io::Error::other(format!("Failed to fetch {}", failed.join(", "))));
+        }
+        Ok(())
+    }
+
+    // resolves the pkgbuilds of a package and everything it transitively depends on at runtime. the closure is
+    // incomplete if any pkgbuild cannot be fetched, so that fails after trying the others
+    pub fn get_runtime_closure(&mut self, pkgname: &str) -> Result<Vec<PackageBuild>, std::io::Error> {
+        let mut closure = vec![self.get_pkgb(pkgname)?];
+        let mut seen = vec![pkgname.to_owned()];
+        let mut queue = closure[0].dependencies.clone();
+        let mut failed = Vec::new();
+
+        while let Some(name) = queue.pop() {
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name.clone());
+            match self.get_pkgb(&name) {
+                Ok(pkgb) => {
+                    queue.extend(pkgb.dependencies.clone());
+                    closure.push(pkgb);
                 }
+                Err(err) => failed.push(format!("{name} ({err})")),
             }
-            .progress_chars("#=--"),
-        );
+        }
 
-        easy.progress_function(move |dl_total, dl_now, _, _| {
-            if dl_total != 0.0 {
-                pb.set_length(dl_total as u64);
+        if !failed.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "Failed to resolve the dependencies of {pkgname}, could not fetch the pkgbuilds of {}",
+                failed.join(", ")
+            )));
+        }
+        Ok(closure)
+    }
+
+    // lists the contents of a package tarball and checks its metadata against the pkgbuild on the master
+    pub fn inspect_pkg(&mut self, master: &Master, cache: &Cache, target: &str) -> Result<(), std::io::Error> {
+        // a package requested by name has to carry that name, a file is checked against whatever it claims to be
+        if Path::new(target).is_file() {
+            return self.inspect_pkg_file(target, None);
+        }
+        if Path::new(&format!("{target}.tar.xz")).is_file() {
+            return self.inspect_pkg_file(&format!("{target}.tar.xz"), Some(target));
+        }
+
+        let dir = create_temp_dir("rranch-inspect")?;
+        let result = self
+            .get_pkg(master, cache, target, Some(&dir.display().to_string()), false)
+            .and_then(|_| self.inspect_pkg_file(&dir.join(format!("{target}.tar.xz")).display().to_string(), Some(target)));
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn inspect_pkg_file(&mut self, path: &str, requested: Option<&str>) -> Result<(), std::io::Error> {
+        let bold = Style::new().bold();
+        let italic = Style::new().italic();
+        let mut archive = tar::Archive::new(xz2::read::XzDecoder::new(std::fs::File::open(path)?));
+        let mut metadata = None;
+        let mut total = 0;
+
+        println!("{}", bold.apply_to(format!("Contents of {path}")));
+        println!("{}", italic.apply_to(format!("{:<10} {:>12} {}", "MODE", "SIZE", "PATH")));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let entry_path = entry.path()?.display().to_string();
+            let size = header.size()?;
+            total += size;
+            println!("{:<10} {:>12} {}", format_mode(header.entry_type(), header.mode()?), HumanBytes(size).to_string(), entry_path);
+
+            if Path::new(&entry_path).file_name().unwrap_or_default() == "leaf.pkg" {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content)?;
+                metadata = Some((entry_path, content));
             }
-            pb.set_position(dl_now as u64);
-            true
-        })?;
+        }
+        println!("{}", bold.apply_to(format!("Total {}", HumanBytes(total))));
 
-        let mut file = std::fs::File::create(format!("{}.tar.xz", pkgname))?;
-        easy.write_function(move |data| match file.write_all(data).map(|_| data.len()) {
-            Ok(size) => Ok(size),
-            Err(err) => {
-                error!("Failed to write content: {}", err);
-                Err(WriteError::Pause)
+        let (metadata_path, metadata) = match metadata {
+            Some(metadata) => metadata,
+            None => {
+                warn!("No package metadata (leaf.pkg) found in {path}");
+                return Ok(());
             }
-        })?;
+        };
+
+        println!("{}", bold.apply_to(format!("Metadata ({metadata_path})")));
+        println!("{}", metadata.trim_end());
+
+        let fields = parse_metadata(&metadata);
+        let name = requested.map(str::to_owned).unwrap_or(fields.get("name").cloned().unwrap_or_default());
+        let pkgb = self.get_pkgb(&name)?;
+
+        let mismatches = metadata_mismatches(&fields, &pkgb);
+        for (key, found, expected) in &mismatches {
+            println!("{}", Style::new().red().apply_to(format!("{key} mismatch: package has '{found}', pkgbuild has '{expected}'")));
+        }
+
+        if !mismatches.is_empty() {
+            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path} does not match the pkgbuild of {name}, the build is stale or wrong")))
+        } else {
+            println!("{}", Style::new().green().apply_to(format!("{path} matches the pkgbuild of {name}")));
+            Ok(())
+        }
+    }
+
+    pub fn get_extra_sources(&mut self) -> Result<Vec<ExtraSourceReceive>, std::io::Error> {
+        let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETMANAGEDEXTRASOURCES", None))?)?)?;
+
+        match resp.statuscode {
+            StatusCode::Ok => Ok(serde_json::from_value::<Vec<ExtraSourceReceive>>(resp.payload)?),
+            StatusCode::InternalServerError | StatusCode::RequestFailure => Err(std::io::Error::other(serde_json::to_string(&resp.payload)?)),
+        }
+    }
+
+    // resolves an extrasource by id first and by filename second
+    pub fn get_extra_source(&mut self, es: &str) -> Result<ExtraSourceReceive, std::io::Error> {
+        let extra_sources = self.get_extra_sources()?;
+
+        if let Some(found) = extra_sources.iter().find(|extra_source| extra_source.id == es) {
+            return Ok(found.clone());
+        }
+
+        let found = extra_sources.iter().filter(|extra_source| extra_source.filename == es).collect::<Vec<&ExtraSourceReceive>>();
+        match found.len() {
+            0 => Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No extrasource with id or filename {es}"))),
+            1 => Ok(found[0].clone()),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Filename {es} is ambiguous, use one of the ids: {}",
+                    found.iter().map(|extra_source| extra_source.id.clone()).collect::<Vec<String>>().join(", ")
+                ),
+            )),
+        }
+    }
+
+    pub fn fetch_extra_source(&mut self, url: &str, es: &str) -> Result<(), std::io::Error> {
+        let extra_source = self.get_extra_source(es)?;
+        download_extra_source(url, &extra_source, Path::new("."))
+    }
+
+    pub fn fetch_extra_sources_for(&mut self, url: &str, pkgname: &str) -> Result<(), std::io::Error> {
+        let pkgb = self.get_pkgb(pkgname)?;
+        let extra_sources = self.get_extra_sources()?;
 
-        let transfer = easy.transfer();
-        Ok(transfer.perform()?)
+        for es_id in pkgb.extra_sources {
+            match extra_sources.iter().find(|extra_source| extra_source.id == es_id) {
+                Some(extra_source) => download_extra_source(url, extra_source, Path::new("."))?,
+                None => warn!("Extrasource {es_id} referenced by {pkgname} does not exist on the master"),
+            }
+        }
+        Ok(())
+    }
+
+    // fetches every managed pkgbuild, one checkout per package
+    pub fn get_all_pkgbs(&mut self) -> Result<Vec<PackageBuild>, std::io::Error> {
+        let mut names = self.get_managed_pkgbs()?;
+        names.sort();
+
+        let progress = ProgressBar::new(names.len() as u64);
+        let mut pkgbs = Vec::new();
+        for name in names {
+            progress.inc(1);
+            pkgbs.push(self.get_pkgb(&name)?);
+        }
+        progress.finish_and_clear();
+        Ok(pkgbs)
+    }
+
+    // maps every extrasource id referenced by a pkgbuild to the packages referencing it. a pkgbuild that cannot be
+    // checked out could reference any extrasource, so the usage is only returned if every one was read
+    pub fn get_extra_source_usage(&mut self) -> Result<HashMap<String, Vec<String>>, std::io::Error> {
+        let mut names = self.get_managed_pkgbs()?;
+        names.sort();
+        let results = self.run_parallel("Checking out", &names, String::clone, |client, name| client.get_pkgb(name))?;
+
+        let mut usage: HashMap<String, Vec<String>> = HashMap::new();
+        let mut failed = Vec::new();
+        for (name, result) in names.into_iter().zip(results) {
+            match result {
+                Ok(pkgb) => pkgb.extra_sources.into_iter().for_each(|es_id| usage.entry(es_id).or_default().push(pkgb.name.clone())),
+                Err(err) => failed.push(format!("{name} ({err})")),
+            }
+        }
+        if !failed.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "Failed to check out {} pkgbuilds, extrasource usage is unknown: {}",
+                failed.len(),
+                failed.join(", ")
+            )));
+        }
+        Ok(usage)
     }
 
     pub fn get_job_log(&mut self, job_id: &str, offset: usize) -> Result<Vec<String>, std::io::Error> {
@@ -239,18 +690,21 @@ impl Client {
 
     pub fn watch_job_log(&mut self, job_id: &str, interval: u64) -> Result<(), std::io::Error> {
         let mut offset = 0;
-        loop {
+        interruptible(|| loop {
             let log = self.get_job_log(job_id, offset)?;
             offset += log.len();
             log.iter().for_each(|line| println!("{line}"));
             if self.get_jobs()?.completedjobs.iter().any(|elem| elem.job_id == *job_id) {
-                break;
+                println!("{}", Style::new().bold().apply_to("Job done!"));
+                return Ok(());
             }
-            std::thread::sleep(Duration::from_secs(interval));
-        }
-        Ok(println!("{}", Style::new().bold().apply_to("Job done!")))
+            if sleep_interruptible(Duration::from_secs(interval)) {
+                return Ok(());
+            }
+        })
     }
 
+    #[allow(clippy::io_other_error)]
     pub fn get_jobs(&mut self) -> Result<JobsStatus, std::io::Error> {
         let resp = serde_json::from_str::<Response>(&self.write_read(&serde_json::to_string(&Request::new("GETJOBSTATUS", None))?)?)?;
 
@@ -260,26 +714,155 @@ impl Client {
         }
     }
 
-    pub fn new_pkgbuild(&mut self, pkgname: &str, editor: &str, templates: HashMap<String, Vec<String>>) -> Result<(), std::io::Error> {
-        let mut pkgb = PackageBuild::new();
-        pkgb.name = pkgname.to_owned();
-        loop {
-            println!("Which template do you want to use?");
-            for (key, value) in templates.clone() {
-                println!("{key}: {:#?}", value)
-            }
-            print!("Choice: ");
-            let choice = get_input()?;
-            match templates.get(&choice) {
-                Some(value) => {
-                    pkgb.build_script = value.to_vec();
-                    break;
+    // creates a pkgbuild from a template, filling its placeholders from the options. missing values are asked for
+    // when editing afterwards, otherwise they are an error
+    pub fn new_pkgbuild(&mut self, pkgname: &str, editor: &str, templates: &[Template], options: NewOptions) -> Result<(), std::io::Error> {
+        // nothing is written if the editor could not be opened afterwards
+        if options.edit {
+            check_editable(&format!("{pkgname}/package.bpb"))?;
+        }
+        let version = options.version.clone().or(options.source.as_deref().and_then(|source| guess_version(pkgname, source)));
+        let mut template = options.template.clone();
+        if let (None, Some(source)) = (&template, &options.source) {
+            template = guess_template(source).unwrap_or_else(|err| {
+                warn!("Failed to inspect {source}: {err}");
+                None
+            });
+            match &template {
+                Some(name) if templates.iter().any(|template| &template.name == name) => info!("Using template {name} guessed from {source}"),
+                Some(name) => {
+                    warn!("Guessed template {name} from {source}, but it does not exist");
+                    template = None;
+                }
+                None => warn!("Could not guess a template from {source}"),
+            }
+        }
+
+        let template = match (template, options.edit) {
+            (Some(name), _) => match templates.iter().find(|template| template.name == name) {
+                Some(template) => template,
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!(
+                            "Template {name} does not exist, available templates: {}",
+                            templates.iter().map(|template| template.name.clone()).collect::<Vec<String>>().join(", ")
+                        ),
+                    ))
+                }
+            },
+            (None, edit) if !edit || !is_interactive() => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No template given and none could be guessed, pass one with --template"))
+            }
+            (None, _) => loop {
+                println!("Which template do you want to use?");
+                for template in templates {
+                    println!("{}: {}", template.name, template.description)
+                }
+                print!("Choice: ");
+                let choice = get_input()?;
+                match templates.iter().find(|template| template.name == choice) {
+                    Some(template) => break template,
+                    None => println!("Invalid input, try again"),
                 }
-                None => println!("Invalid input, try again"),
+            },
+        };
+
+        let mut values = HashMap::from([("name".to_owned(), pkgname.to_owned())]);
+        for (key, value) in [("version", &version), ("source", &options.source), ("description", &options.description)] {
+            if let Some(value) = value {
+                values.insert(key.to_owned(), value.clone());
             }
         }
-        pkgb.create_workdir()?;
-        self.edit_local(format!("{pkgname}/package.bpb").as_str(), editor)
+        values.extend(options.values.clone());
+        for variable in template.get_variables() {
+            if let Entry::Vacant(entry) = values.entry(variable) {
+                if !options.edit {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Template {} needs a value for {}, pass it with --set {}=...", template.name, entry.key(), entry.key()),
+                    ));
+                }
+                let value = get_input_default(&format!("Value for {}", entry.key()), "")?;
+                entry.insert(value);
+            }
+        }
+
+        let mut pkgb = template.render(&values)?;
+        pkgb.name = pkgname.to_owned();
+        if let Some(version) = version {
+            pkgb.version = version;
+        }
+        if let Some(source) = options.source {
+            pkgb.source = source;
+        }
+        if let Some(description) = options.description {
+            pkgb.description = description;
+        }
+        for dependency in options.dependencies {
+            if !pkgb.dependencies.contains(&dependency) {
+                pkgb.dependencies.push(dependency);
+            }
+        }
+        for dependency in options.build_dependencies {
+            if !pkgb.build_dependencies.contains(&dependency) {
+                pkgb.build_dependencies.push(dependency);
+            }
+        }
+        pkgb.create_workdir(options.force)?;
+
+        let path = format!("{pkgname}/package.bpb");
+        if options.edit {
+            self.edit_local(&path, editor)
+        } else if options.submit {
+            self.submit(&path)
+        } else {
+            info!("Wrote {path}");
+            Ok(())
+        }
+    }
+
+    // sets a new version on the master's pkgbuild, also inside its source url, and submits it after showing the changes
+    pub fn bump(&mut self, pkgname: &str, version: &str, policy: RealVersionPolicy, rebuild: bool) -> Result<(), std::io::Error> {
+        let old = self.get_pkgb(pkgname)?;
+        if old.version == version {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{pkgname} already has version {version}")));
+        }
+
+        let mut new = old.clone();
+        new.version = version.to_owned();
+        new.source = match replace_version(&old.source, &old.version, version) {
+            Some(source) => source,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Version {} not found in source {}, edit the pkgbuild instead", old.version, old.source),
+                ))
+            }
+        };
+        new.real_version = match policy {
+            RealVersionPolicy::Reset => "0".to_owned(),
+            RealVersionPolicy::Increment => match old.real_version.parse::<u64>() {
+                Ok(real_version) => (real_version + 1).to_string(),
+                Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("real_version {} is not a number", old.real_version))),
+            },
+            RealVersionPolicy::Keep => old.real_version.clone(),
+        };
+
+        print_diff(
+            &old.to_vec()?.join("\n"),
+            &new.to_vec()?.join("\n"),
+            &format!("{pkgname} {}-{}", old.version, old.real_version),
+            &format!("{pkgname} {}-{}", new.version, new.real_version),
+        );
+        if !get_yn(&format!("Submit {pkgname} {version}?"), true)? {
+            info!("Aborted bump due to user choice");
+            return Ok(());
+        }
+        if self.submit_pkgb(new)? && rebuild {
+            self.build(pkgname, true)?;
+        }
+        Ok(())
     }
 
     pub fn get_pkg_with_name(&mut self, pkgname: &str) -> Result<(), std::io::Error> {
@@ -328,3 +911,194 @@ impl Client {
         Ok(combined)
     }
 }
+
+// the filename comes from the master and is written to dir, so it must not name a path
+fn download_extra_source(url: &str, extra_source: &ExtraSourceReceive, dir: &Path) -> Result<(), std::io::Error> {
+    let mut components = Path::new(&extra_source.filename).components();
+    if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Refusing to download extrasource {} to {}, not a plain filename", extra_source.id, extra_source.filename),
+        ));
+    }
+
+    println!("Downloading {} ({})...", extra_source.filename, extra_source.id);
+    // the master may not publish checksums of extrasources
+    download(
+        &format!("{}?get=extrasource&esid={}", url, extra_source.id),
+        &dir.join(&extra_source.filename).display().to_string(),
+        Checksum::IfPublished(&format!("{}?get=hash&esid={}", url, extra_source.id)),
+    )
+}
+
+// key=value pairs of a leaf.pkg
+fn parse_metadata(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect()
+}
+
+// fields of a leaf.pkg that differ from the pkgbuild, as key, found and expected
+fn metadata_mismatches(fields: &HashMap<String, String>, pkgb: &PackageBuild) -> Vec<(&'static str, String, String)> {
+    [("name", &pkgb.name), ("version", &pkgb.version), ("real_version", &pkgb.real_version)]
+        .into_iter()
+        .map(|(key, expected)| (key, fields.get(key).cloned().unwrap_or_default(), expected.clone()))
+        .filter(|(_, found, expected)| found != expected)
+        .collect()
+}
+
+// decompresses the whole package, which has xz check every block, and compares its leaf.pkg against the pkgbuild
+fn verify_package(path: &str, pkgb: &PackageBuild) -> Result<(), std::io::Error> {
+    let corrupt = |err: std::io::Error| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path} is corrupt: {err}"));
+    let mut archive = tar::Archive::new(xz2::read::XzDecoder::new(std::fs::File::open(path)?));
+    let mut fields = None;
+    for entry in archive.entries().map_err(corrupt)? {
+        let mut entry = entry.map_err(corrupt)?;
+        if entry.path().map_err(corrupt)?.file_name().unwrap_or_default() == "leaf.pkg" {
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut content).map_err(corrupt)?;
+            fields = Some(parse_metadata(&content));
+        }
+    }
+    // tar stops at its end marker, the rest of the xz stream still has to be checked
+    std::io::copy(&mut archive.into_inner(), &mut std::io::sink()).map_err(corrupt)?;
+
+    let Some(fields) = fields else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path} has no package metadata (leaf.pkg)")));
+    };
+    let mismatches = metadata_mismatches(&fields, pkgb);
+    if !mismatches.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{path} does not match the pkgbuild of {}: {}",
+                pkgb.name,
+                mismatches
+                    .iter()
+                    .map(|(key, found, expected)| format!("{key} is '{found}' instead of '{expected}'"))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        ));
+    }
+    trace!("Verified {path}");
+    Ok(())
+}
+
+// fetches a package into dir through the local cache and mirror, returns the path of the tarball. verify needs the
+// pkgbuild to check the package against
+fn fetch_package(master: &Master, cache: &Cache, pkgname: &str, pkgb: Option<&PackageBuild>, dir: &str, verify: bool, pb: &ProgressBar) -> Result<String, std::io::Error> {
+    let path = format!("{dir}/{pkgname}.tar.xz");
+    if verify && pkgb.is_none() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Cannot verify {pkgname} without its pkgbuild")));
+    }
+
+    // the cache is keyed by the version of the pkgbuild and what the server currently tells about the package
+    let url = master.get_package_url(pkgname);
+    let key = match pkgb {
+        Some(pkgb) => match fetch_validator(&url)? {
+            Some(validator) => Some((pkgb, validator)),
+            None => {
+                info!("{url} reports neither an etag nor a last-modified date, a cached copy of {pkgname} is only checked by its version");
+                Some((pkgb, String::new()))
+            }
+        },
+        None => None,
+    };
+    let mut cached = match &key {
+        Some((pkgb, validator)) => cache::restore(cache, pkgname, &pkgb.version, &pkgb.real_version, validator, &path)?,
+        None => false,
+    };
+    // cached packages are verified just like downloaded ones, a damaged entry is dropped and fetched again
+    if let (true, true, Some((pkgb, validator))) = (verify, cached, &key) {
+        if let Err(err) = verify_package(&path, pkgb) {
+            warn!("Cached {pkgname} failed verification, fetching it again: {err}");
+            std::fs::remove_file(&path)?;
+            cache::evict(cache, pkgname, &pkgb.version, &pkgb.real_version, validator)?;
+            cached = false;
+        }
+    }
+
+    if cached {
+        pb.finish_and_clear();
+    } else {
+        let hash_url = master.get_package_hash_url(pkgname);
+        let checksum = match &hash_url {
+            Some(hash_url) if verify => Checksum::Required(hash_url),
+            _ => Checksum::Skip,
+        };
+        download_with(&url, &path, checksum, pb)?;
+        if let (true, Some(pkgb)) = (verify, pkgb) {
+            if let Err(err) = verify_package(&path, pkgb) {
+                std::fs::remove_file(&path)?;
+                return Err(err);
+            }
+        }
+    }
+    if let (false, Some((pkgb, validator))) = (cached, &key) {
+        cache::store(cache, pkgname, &pkgb.version, &pkgb.real_version, validator, &path)?;
+    }
+
+    if let Some(mirror) = cache.get_mirror() {
+        cache::mirror_package(&mirror, pkgname, &path)?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::Path,
+    };
+
+    use super::download_extra_source;
+    use crate::json::extra_source::ExtraSourceReceive;
+
+    // a master serving content for every ?get=extrasource request and a 404 for anything else, eg. ?get=hash
+    fn serve_extra_source(content: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map(Result::unwrap);
+                let target = lines.next().unwrap_or_default();
+                lines.take_while(|line| !line.is_empty()).for_each(drop);
+                let (status, body) = if target.contains("get=extrasource") { ("200 OK", content) } else { ("404 Not Found", b"not found".as_slice()) };
+                let _ = write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                let _ = stream.write_all(body);
+            }
+        });
+        url
+    }
+
+    fn extra_source(filename: &str) -> ExtraSourceReceive {
+        ExtraSourceReceive {
+            id: "2b5c4a1e".to_owned(),
+            filename: filename.to_owned(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn downloads_extra_sources_without_a_published_checksum() {
+        let url = serve_extra_source(b"--- a/configure\n+++ b/configure\n");
+        let dir = std::env::temp_dir().join(format!("rranch-extrasource-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        download_extra_source(&url, &extra_source("fix-configure.patch"), &dir).unwrap();
+        assert_eq!(std::fs::read(dir.join("fix-configure.patch")).unwrap(), b"--- a/configure\n+++ b/configure\n");
+    }
+
+    #[test]
+    fn refuses_extra_source_filenames_that_are_paths() {
+        for filename in ["../fix.patch", "/etc/passwd", "patches/fix.patch", ""] {
+            assert!(download_extra_source("http://127.0.0.1:9/", &extra_source(filename), Path::new(".")).is_err(), "{filename}");
+        }
+    }
+}
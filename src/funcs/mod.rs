@@ -1,2 +1,4 @@
+pub mod bundle;
 pub mod coms;
 pub mod helpers;
+pub mod sync;
@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use console::Style;
+use log::{error, info, warn};
+
+use crate::{
+    json::pkgbuild::PackageBuild,
+    structs::{client::Client, config::config_master::Master, sync_state::SyncState},
+    util::{download::sha256_of, funcs::get_pkgbs},
+};
+
+impl Client {
+    // writes every pkgbuild of the master to <repo>/<name>/package.bpb and commits the changes. pkgbuilds changed in
+    // the repo and on the master since the last sync are conflicts and left alone unless force is set
+    pub fn sync_pull(&mut self, master: &Master, name: &str, repo: &str, force: bool) -> Result<(), std::io::Error> {
+        if git(repo, &["rev-parse", "--git-dir"]).is_err() {
+            info!("{repo} is not a git repository, initializing it");
+            std::fs::create_dir_all(repo)?;
+            git(repo, &["init"])?;
+        }
+        let state_path = get_state_path(repo)?;
+        let mut state = load_state(&state_path, master)?;
+
+        let mut changes: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        let mut conflicts = Vec::new();
+        let pkgbs = self.get_all_pkgbs()?;
+        for pkgb in &pkgbs {
+            let path = get_pkgb_path(repo, &pkgb.name);
+            let remote = get_hash(pkgb)?;
+            let local = if path.exists() { Some(get_hash(&PackageBuild::from_str(&std::fs::read_to_string(&path)?)?)?) } else { None };
+            let base = state.pkgbuilds.get(&pkgb.name);
+
+            if local.as_ref() == Some(&remote) {
+                state.pkgbuilds.insert(pkgb.name.clone(), remote);
+                continue;
+            }
+            // local changes alone are left for push
+            if base == Some(&remote) {
+                continue;
+            }
+            if local.is_some() && local.as_ref() != base && !force {
+                conflicts.push(pkgb.name.clone());
+                continue;
+            }
+
+            std::fs::create_dir_all(path.parent().unwrap_or(Path::new(repo)))?;
+            std::fs::write(&path, pkgb.to_vec()?.join("\n"))?;
+            changes.entry(if local.is_some() { "Updated" } else { "Added" }).or_default().push(pkgb.name.clone());
+            state.pkgbuilds.insert(pkgb.name.clone(), remote);
+        }
+
+        let removed = state.pkgbuilds.keys().filter(|known| !pkgbs.iter().any(|pkgb| &pkgb.name == *known)).cloned().collect::<Vec<String>>();
+        for pkgname in removed {
+            let path = get_pkgb_path(repo, &pkgname);
+            if path.exists() {
+                let local = get_hash(&PackageBuild::from_str(&std::fs::read_to_string(&path)?)?)?;
+                if state.pkgbuilds.get(&pkgname) != Some(&local) && !force {
+                    conflicts.push(pkgname);
+                    continue;
+                }
+                std::fs::remove_file(&path)?;
+                // the directory may hold more than the pkgbuild
+                let _ = std::fs::remove_dir(path.parent().unwrap_or(Path::new(repo)));
+                changes.entry("Removed").or_default().push(pkgname.clone());
+            }
+            state.pkgbuilds.remove(&pkgname);
+        }
+
+        let count = changes.values().map(Vec::len).sum::<usize>();
+        if count > 0 {
+            let paths = changes.values().flatten().map(|pkgname| format!("{pkgname}/package.bpb")).collect::<Vec<String>>();
+            let message = format!(
+                "Sync {count} pkgbuilds from {name}\n\n{}",
+                changes.iter().map(|(kind, names)| format!("{kind}: {}", names.join(", "))).collect::<Vec<String>>().join("\n")
+            );
+            git(repo, &[&["add", "-A", "--"], paths.iter().map(String::as_str).collect::<Vec<&str>>().as_slice()].concat())?;
+            // only the synced files, anything else staged stays staged
+            git(repo, &[&["commit", "--only", "-m", &message, "--"], paths.iter().map(String::as_str).collect::<Vec<&str>>().as_slice()].concat())?;
+        }
+        state.write(&state_path)?;
+
+        let bold = Style::new().bold();
+        for (kind, names) in &changes {
+            println!("{} {}", bold.apply_to(format!("{kind}:")), names.join(", "));
+        }
+        println!("{}", bold.apply_to(format!("Pulled {count} of {} pkgbuilds into {repo}", pkgbs.len())));
+        check_conflicts(&conflicts)
+    }
+
+    // submits the pkgbuilds changed in the repo since the last complete push, everything on the first one.
+    // pkgbuilds also changed on the master since the last sync are conflicts and skipped unless force is set
+    pub fn sync_push(&mut self, master: &Master, repo: &str, force: bool) -> Result<(), std::io::Error> {
+        let state_path = get_state_path(repo)?;
+        let mut state = load_state(&state_path, master)?;
+
+        let mut paths = match &state.commit {
+            Some(commit) => {
+                let changed = git(repo, &["diff", "--name-only", "--relative", "--diff-filter=ACMR", commit.as_str()])?;
+                let untracked = git(repo, &["ls-files", "--others", "--exclude-standard"])?;
+                changed.lines().chain(untracked.lines()).filter(|file| file.ends_with(".bpb")).map(|file| Path::new(repo).join(file).display().to_string()).collect()
+            }
+            None => get_pkgbs(repo)?,
+        };
+        paths.sort();
+        paths.dedup();
+
+        let managed = self.get_managed_pkgbs()?;
+        let mut submitted = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut incomplete = false;
+        for path in paths {
+            let pkgb = PackageBuild::from_str(&std::fs::read_to_string(&path)?)?;
+            let local = get_hash(&pkgb)?;
+            let remote = if managed.contains(&pkgb.name) { Some(get_hash(&self.get_pkgb(&pkgb.name)?)?) } else { None };
+
+            if remote.as_ref() == Some(&local) {
+                state.pkgbuilds.insert(pkgb.name.clone(), local);
+                continue;
+            }
+            if remote.is_some() && remote.as_ref() != state.pkgbuilds.get(&pkgb.name) && !force {
+                conflicts.push(pkgb.name.clone());
+                continue;
+            }
+
+            let pkgname = pkgb.name.clone();
+            match self.submit_pkgb(pkgb) {
+                Ok(true) => {
+                    if !self.dry_run {
+                        state.pkgbuilds.insert(pkgname.clone(), local);
+                    }
+                    submitted.push(pkgname);
+                }
+                Ok(false) => incomplete = true,
+                Err(err) => {
+                    error!("Failed to submit {path}: {err}");
+                    incomplete = true;
+                }
+            }
+        }
+
+        if self.dry_run {
+            info!("Dry run, the sync state was not updated");
+        } else {
+            if !incomplete && conflicts.is_empty() {
+                state.commit = git(repo, &["rev-parse", "HEAD"]).ok();
+            }
+            state.write(&state_path)?;
+        }
+
+        println!("{}", Style::new().bold().apply_to(format!("Pushed {} pkgbuilds from {repo}", submitted.len())));
+        if !submitted.is_empty() {
+            println!("{}", submitted.join(", "));
+        }
+        check_conflicts(&conflicts)?;
+        if incomplete {
+            return Err(std::io::Error::other("Not every pkgbuild was submitted, they are tried again on the next push"));
+        }
+        Ok(())
+    }
+}
+
+// runs git inside repo, returns its trimmed stdout
+fn git(repo: &str, args: &[&str]) -> Result<String, std::io::Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|err| std::io::Error::new(err.kind(), format!("Failed to run git: {err}")))?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!("git {} failed: {}", args.first().unwrap_or(&""), String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// kept in the git directory, so it is neither committed nor shared between clones
+fn get_state_path(repo: &str) -> Result<String, std::io::Error> {
+    let git_dir = git(repo, &["rev-parse", "--git-dir"])?;
+    Ok(Path::new(repo).join(git_dir).join("rranch-sync.toml").display().to_string())
+}
+
+// hashes of another master say nothing about this one
+fn load_state(path: &str, master: &Master) -> Result<SyncState, std::io::Error> {
+    let address = format!("{}:{}", master.get_addr(), master.get_port());
+    let mut state = SyncState::load(path)?;
+    if !state.master.is_empty() && state.master != address {
+        warn!("{path} was synced with {}, starting over for {address}", state.master);
+        state = SyncState::default();
+    }
+    state.master = address;
+    Ok(state)
+}
+
+fn get_pkgb_path(repo: &str, pkgname: &str) -> PathBuf {
+    Path::new(repo).join(pkgname).join("package.bpb")
+}
+
+// hashed in the form checkout writes, so formatting differences in the repo do not count as changes
+fn get_hash(pkgb: &PackageBuild) -> Result<String, std::io::Error> {
+    Ok(sha256_of(pkgb.to_vec()?.join("\n").as_bytes()))
+}
+
+fn check_conflicts(conflicts: &[String]) -> Result<(), std::io::Error> {
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    Err(std::io::Error::other(format!(
+        "{} pkgbuilds changed both in the repo and on the master since the last sync: {}. `sync pull --force` takes the master's version, \
+         `sync push --force` submits the repo's, eg. after merging by hand",
+        conflicts.len(),
+        conflicts.join(", ")
+    )))
+}
@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct AuthRequest {
     pub machine_identifier: String,
     pub machine_type: String,
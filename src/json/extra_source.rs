@@ -36,7 +36,7 @@ use std::fmt::Display;
 
 use serde_derive::Deserialize;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct ExtraSourceReceive {
     pub id: String,
     pub filename: String,
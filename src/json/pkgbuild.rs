@@ -3,7 +3,7 @@ use serde_derive::{Deserialize, Serialize};
 
 use crate::util::funcs::get_yn;
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct PackageBuild {
     pub name: String,
     pub description: String,
@@ -76,14 +76,43 @@ impl PackageBuild {
         Ok(lines)
     }
 
-    pub fn create_workdir(&mut self) -> Result<(), std::io::Error> {
+    // added and removed entries of the dependency and extra source lists, eg. "+ builddep: zlib"
+    pub fn get_list_changes(&self, new: &PackageBuild) -> Vec<String> {
+        let lists = [
+            ("dep", &self.dependencies, &new.dependencies),
+            ("builddep", &self.build_dependencies, &new.build_dependencies),
+            ("crossdep", &self.cross_dependencies, &new.cross_dependencies),
+            ("extrasource", &self.extra_sources, &new.extra_sources),
+        ];
+
+        let mut changes = Vec::new();
+        for (kind, old, new) in lists {
+            changes.extend(old.iter().filter(|entry| !new.contains(entry)).map(|entry| format!("- {kind}: {entry}")));
+            changes.extend(new.iter().filter(|entry| !old.contains(entry)).map(|entry| format!("+ {kind}: {entry}")));
+        }
+        changes
+    }
+
+    // compares in the form checkout writes, so the formatting of local files does not count
+    pub fn is_same(&self, other: &PackageBuild) -> Result<bool, std::io::Error> {
+        Ok(self.to_vec()? == other.to_vec()?)
+    }
+
+    // writes ./<name>/package.bpb, keeping anything else in the directory
+    pub fn write_workdir(&self) -> Result<(), std::io::Error> {
+        std::fs::create_dir_all(&self.name)?;
+        std::fs::write(format!("{}/package.bpb", self.name), self.to_vec()?.join("\n"))
+    }
+
+    // writes ./<name>/package.bpb, an existing one is only replaced if force is set or the user agrees
+    pub fn create_workdir(&mut self, force: bool) -> Result<(), std::io::Error> {
         let path = self.name.as_str();
 
         if std::fs::metadata(path).is_ok() {
-            if get_yn("Package build exists locally, do you want to overwrite it?", false)? {
+            if force || get_yn("Package build exists locally, do you want to overwrite it?", false)? {
                 std::fs::remove_dir_all(path)?;
             } else {
-                trace!("Did not write packagebuild");
+                warn!("Did not overwrite {path}, pass --force to replace it");
                 return Ok(());
             }
         }
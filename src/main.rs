@@ -1,10 +1,22 @@
-use std::process::exit;
+use std::{io::IsTerminal, process::exit};
 
-use args::argparser::{Arg, ArgParser};
-use log::{debug, error, trace};
-use structs::{client::Client, config::config_main::Config};
+use args::{
+    cli::{self, CacheCommand, ClientCommand, Command, ConfigCommand, ConvertFrom, ExtraSourceCommand, JobCommand, Parsed, PkgCommand, PkgbCommand, SyncCommand},
+    legacy,
+};
+use log::{debug, error, trace, warn, LevelFilter};
+use structs::{
+    client::Client,
+    config::config_main::{user_config_path, Config},
+    template::{load_templates, show_templates, NewOptions},
+};
 
-use crate::util::funcs::configure;
+use crate::util::{
+    arch, batch, cache, completion,
+    funcs::{configure, get_yn, is_interactive, set_interaction, Interaction},
+    init::init,
+    shell,
+};
 
 mod args;
 mod funcs;
@@ -13,129 +25,98 @@ mod structs;
 mod util;
 
 fn main() -> std::io::Result<()> {
-    let confpath = format!("{}/.config/rranch.toml", dirs::home_dir().unwrap_or_default().to_str().unwrap_or_default());
-    let config = Config::new_from_cfg(&confpath, 1)?;
-    std::env::set_var("rranch_log", config.get_client().get_loglevel());
+    // the loglevel is only known once the config is resolved, so the logger lets everything through and max_level filters
+    std::env::set_var("rranch_log", "trace");
     pretty_env_logger::init_custom_env("rranch_log");
-    let mut argparser = ArgParser::new(Vec::new(), Some("The branch client rewritten in Rust with Protocol version 2 (json)"), Vec::new());
+    log::set_max_level(LevelFilter::Info);
 
-    let args = Vec::from([
-        Arg::new("c", "checkout", "Fetches pkgbuild", Some("name")),
-        Arg::new("s", "submit", "Submits pkgbuild", Some("path")),
-        Arg::new("n", "new", "Creates new pkgb", Some("name")),
-        Arg::new("rb", "releasebuild", "Releasebuilds pkg", Some("name")),
-        Arg::new("cb", "crossbuild", "Crossbuilds pkg", Some("name")),
-        Arg::new("jl", "job-log", "Joblog for job", Some("job_id")),
-        Arg::new("sl", "sys-log", "Fetches syslog", None),
-        Arg::new("depds", "dependers", "Dependers", Some("name")),
-        Arg::new("deps", "dependencies", "Dependencies", Some("name")),
-        Arg::new("rd", "rebuild-dependers", "Rebuild dependers", Some("name")),
-        Arg::new("rds", "rebuild-dependencies", "Rebuild dependencies", Some("name")),
-        Arg::new("rrds", "rebuild-build-dependencies", "Rebuild build dependencies", Some("name")),
-        Arg::new("rcds", "rebuild-cross-dependencies", "Rebuild cross dependencies", Some("name")),
-        Arg::new("js", "job-status", "Shows jobs", None),
-        Arg::new("wj", "watch-jobs", "Periodic jobstatus", Some("interval")),
-        Arg::new("llc", "latest-log-complete", "Latest job log", None),
-        Arg::new("llr", "latest-log-running", "Latest job log", None),
-        Arg::new("cs", "client-status", "Shows active clients", None),
-        Arg::new("ci", "client-info", "Shows client info", Some("name")),
-        Arg::new("mpkg", "managed-pkgs", "Shows pkg status", None),
-        Arg::new("mpkgb", "managed-pkgbs", "Shows pkgb status", None),
-        Arg::new("d", "diff", "pkgs / pkgbs diff", None),
-        Arg::new("cc", "clear-completed", "Clear completed jobs", None),
-        Arg::new("cq", "cancel-queued", "Cancels queued job", Some("id")),
-        Arg::new("caq", "cancel-all-queued", "Cancels all queud jobs", None),
-        Arg::new("ssr", "submit-solution-release", "Submits release solution", Some("path")),
-        Arg::new("ssc", "submit-solution-cross", "Submits cross solution", Some("path")),
-        Arg::new("e", "edit", "Opens pkgb with editor", Some("name")),
-        Arg::new("el", "edit-local", "Edits local pkgb", Some("path")),
-        Arg::new("rm", "remove-pkg", "Removes pkg", Some("name")),
-        Arg::new("es", "extrasources", "Shows extrasources", None),
-        Arg::new("res", "remove-extrasource", "Removes extrasource", Some("id")),
-        Arg::new("ses", "submit-extrasource", "Submits extrasource", Some("path")),
-        Arg::new("ex", "export", "Exports all pkgbs", None),
-        Arg::new("im", "import", "Imports all pkgbs", Some("path")),
-        Arg::new("cf", "configure", "configures client", None),
-        Arg::new("fp", "fetch-pkg", "Downloads pkg", Some("name")),
-        Arg::new("f", "find", "Tries to find pkg with name", Some("name")),
-        Arg::new("i", "info", "Fetches pkg info", Some("name")),
-    ]);
+    let argvs = match legacy::translate(std::env::args().collect()) {
+        Ok(argvs) => argvs,
+        Err(err) => {
+            error!("{err}, see --help");
+            exit(2)
+        }
+    };
+    // the old flags can run several commands at once, they share the globals
+    let commands = argvs.into_iter().map(cli::parse).collect::<Vec<_>>();
+    let Some(Parsed { cli: first, overrides: flags, .. }) = commands.first() else {
+        return Ok(());
+    };
 
-    argparser.define_args(args);
-    argparser.parse_args();
+    let profile = first.profile.clone().or(std::env::var("RRANCH_PROFILE").ok());
+    set_interaction(first.get_interaction().or(Interaction::from_env()).unwrap_or(Interaction::Ask));
+    // completion runs on every tab press, it must neither prompt nor log
+    match first.command {
+        Command::Completions { shell } => return completion::print_script(shell),
+        Command::Complete { kind } => {
+            log::set_max_level(LevelFilter::Off);
+            return match Config::read(1, profile.as_deref(), flags) {
+                Ok(config) => completion::complete(&config, kind),
+                Err(_) => Ok(()),
+            };
+        }
+        _ => (),
+    }
+    let init_requested = commands.iter().any(|Parsed { cli, .. }| matches!(cli.command, Command::Config(ConfigCommand::Init)));
+    if !user_config_path().exists() && !init_requested && std::io::stdin().is_terminal() && is_interactive() {
+        let setup = get_yn(&format!("No config found at {}. Set it up now?", user_config_path().display()), true)
+            .and_then(|setup| if setup { init(&user_config_path(), &Config::default()) } else { Ok(()) });
+        if let Err(err) = setup {
+            error!("{err}");
+            exit(-1)
+        }
+    }
 
-    let mut client = match Client::new(&config.get_master().get_addr(), config.get_master().get_port() as u16) {
-        Ok(client) => client,
+    let config = match Config::load(1, profile.as_deref(), flags) {
+        Ok(config) => config,
+        // a broken config can still be fixed
+        Err(err) if commands.iter().any(|Parsed { cli, .. }| matches!(cli.command, Command::Config(ConfigCommand::Init | ConfigCommand::Edit))) => {
+            warn!("Failed to load config: {err}");
+            Config::default()
+        }
         Err(err) => {
-            error!("Failed to connect to master: {err}");
+            error!("Failed to load config: {err}");
             exit(-1)
         }
     };
+    log::set_max_level(match config.get_client().get_loglevel().to_lowercase().as_str() {
+        "none" => LevelFilter::Off,
+        level => level.parse().unwrap_or(LevelFilter::Info),
+    });
 
-    match client.auth(
-        &config.get_client().get_name(),
-        &config.get_client().get_type(),
-        &config.get_master().get_authkey(),
-        config.get_client().get_protver(),
-    ) {
-        Ok(response) => debug!("{}", response.logon_message),
-        Err(err) => {
-            error!("Failed to authenticate: {err}");
+    let problems = config.validate(
+        commands.iter().any(|Parsed { cli, .. }| !cli.command.is_local()),
+        commands.iter().any(|Parsed { cli, .. }| cli.command.opens_editor()),
+    );
+    if !problems.is_empty() {
+        // commands used to fix the config still run
+        if commands.iter().any(|Parsed { cli, .. }| cli.command.fixes_config()) {
+            problems.iter().for_each(|problem| warn!("{problem}"));
+        } else {
+            problems.iter().for_each(|problem| error!("{problem}"));
+            error!("Invalid config, fix it with `rranch config edit` or `rranch config init`, or check where values come from with `rranch config show --origin`");
             exit(-1)
         }
-    };
+    }
 
-    for parsed in argparser.get_parsed() {
-        debug!("Trying to handle {}", parsed.0);
-        let result = match parsed.0.clone().as_str() {
-            "--checkout" => client.checkout(parsed.1.unwrap_or_default().as_str()),
-            "--submit" => client.submit(parsed.1.unwrap_or_default().as_str()),
-            "--new" => client.new_pkgbuild(parsed.1.unwrap_or_default().as_str(), &config.get_client().get_editor(), config.get_templates().get_templates()),
-            "--releasebuild" => client.build(parsed.1.unwrap_or_default().as_str(), true),
-            "--crossbuild" => client.build(parsed.1.unwrap_or_default().as_str(), false),
-            "--job-log" => client.watch_job_log(parsed.1.unwrap_or_default().as_str(), 1),
-            "--sys-log" => client.show_sys_log(),
-            "--dependers" => client.show_dependers(parsed.1.unwrap_or_default().as_str()),
-            "--dependencies" => client.show_dependencies(parsed.1.unwrap_or_default().as_str()),
-            "--rebuilddependers" => client.rebuild_dependers(parsed.1.unwrap_or_default().as_str()),
-            "--rebuild-dependencies" => client.rebuild_dependencies(parsed.1.unwrap_or_default().as_str(), structs::deps::Deps::Runtime),
-            "--rebuild-build-dependencies" => client.rebuild_dependencies(parsed.1.unwrap_or_default().as_str(), structs::deps::Deps::Build),
-            "--rebuild-cross-dependencies" => client.rebuild_dependencies(parsed.1.unwrap_or_default().as_str(), structs::deps::Deps::Cross),
-            "--job-status" => client.show_jobs_status(false),
-            "--watch-jobs" => client.watch_jobs(parsed.1.unwrap_or_default().as_str()),
-            "--latest-log-complete" => client.show_latest_complete_log(),
-            "--latest-log-running" => client.show_latest_running_log(),
-            "--client-status" => client.show_clients(),
-            "--client-info" => client.show_client_info(parsed.1.unwrap_or_default().as_str()),
-            "--managed-pkgs" => client.show_managed_pkgs(),
-            "--managed-pkgbs" => client.show_managed_pkgbs(),
-            "--diff" => client.show_diff(),
-            "--clear-completed" => client.clear_completed(),
-            "--cancel-queued" => client.cancel_queued(Some(parsed.1.unwrap_or_default().as_str())),
-            "--cancel-all-queued" => client.cancel_queued(None),
-            "--submit-solution-release" => client.submit_solution(parsed.1.unwrap_or_default().as_str(), true),
-            "--submit-solution-cross" => client.submit_solution(parsed.1.unwrap_or_default().as_str(), false),
-            "--edit" => client.edit(parsed.1.unwrap_or_default().as_str(), &config.get_client().get_editor()),
-            "--edit-local" => client.edit_local(parsed.1.unwrap_or_default().as_str(), &config.get_client().get_editor()),
-            "--remove-pkg" => client.remove_pkg(parsed.1.unwrap_or_default().as_str()),
-            "--extra-sources" => client.show_extra_sources(),
-            "--extrasources" => client.show_extra_sources(),
-            "--remove-extrasource" => client.remove_extra_source(parsed.1.unwrap_or_default().as_str()),
-            "--submit-extrasource" => client.submit_extra_source(parsed.1.unwrap_or_default().as_str()),
-            "--export" => client.export(),
-            "--import" => client.import(parsed.1.unwrap_or_default().as_str()),
-            "--configure" => configure(&confpath, &config.get_client().get_editor()),
-            "--help" => Ok(()),
-            "--fetch-pkg" => client.get_pkg(&config.get_master().get_fetch_url(), parsed.1.unwrap_or_default().as_str()),
-            "--find" => client.get_pkg_with_name(parsed.1.unwrap_or_default().as_str()),
-            "--info" => client.get_info(parsed.1.unwrap_or_default().as_str()),
-            arg => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Unimplemented argument {}", arg))),
+    let dry_run = first.dry_run;
+    // connected on the first command that needs the master, so local commands work without one
+    let mut client: Option<Client> = None;
+
+    for Parsed { cli, name, .. } in &commands {
+        debug!("Trying to handle {name}");
+        let result = if cli.command.is_local() {
+            handle_local(&config, &cli.command)
+        } else {
+            handle(client.get_or_insert_with(|| connect(&config, dry_run)), &config, &cli.command)
         };
         match result {
-            Ok(_) => trace!("Handled {}", parsed.0),
+            Ok(_) => trace!("Handled {name}"),
             Err(err) => {
-                error!("Failed on {}, reason: {}", parsed.0, err);
-                client.shutdown()?;
+                error!("Failed on {name}, reason: {err}");
+                if let Some(client) = client.as_mut() {
+                    client.shutdown()?;
+                }
                 exit(-1);
             }
         }
@@ -143,3 +124,127 @@ fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+fn connect(config: &Config, dry_run: bool) -> Client {
+    debug!(
+        "Connecting to {}:{} (profile {})",
+        config.get_master().get_addr(),
+        config.get_master().get_port(),
+        config.get_profile_name().unwrap_or("none".to_owned())
+    );
+    let authkey = match config.get_authkey() {
+        Ok(authkey) => authkey,
+        Err(err) => {
+            error!("{err}");
+            exit(-1)
+        }
+    };
+    let mut client = match Client::new(&config.get_master().get_addr(), config.get_master().get_port()) {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Failed to connect to master: {err}");
+            exit(-1)
+        }
+    };
+
+    match client.auth(&config.get_client().get_name(), &config.get_client().get_type(), &authkey, config.get_client().get_protver()) {
+        Ok(response) => debug!("{}", response.logon_message),
+        Err(err) => {
+            error!("Failed to authenticate: {err}");
+            exit(-1)
+        }
+    };
+    client.dry_run = dry_run;
+    client
+}
+
+fn handle_local(config: &Config, command: &Command) -> Result<(), std::io::Error> {
+    match command {
+        Command::Config(ConfigCommand::Init) => init(&user_config_path(), config),
+        Command::Config(ConfigCommand::Edit) => configure(&user_config_path().to_string_lossy(), &config.get_client().get_editor()),
+        Command::Config(ConfigCommand::Show { origin }) => config.show_config(*origin),
+        Command::Config(ConfigCommand::Profiles) => config.show_profiles(),
+        Command::Pkgb(PkgbCommand::Templates) => show_templates(&load_templates(&config.get_template_dir(), &config.get_templates().get_templates())?),
+        Command::Cache(CacheCommand::Ls) => cache::show_cache(&config.get_cache()),
+        Command::Cache(CacheCommand::Prune) => cache::prune(&config.get_cache(), true),
+        Command::Cache(CacheCommand::Clear) => cache::clear_cache(&config.get_cache()),
+        Command::Convert { from: ConvertFrom::Arch, path, force } => arch::convert(path, *force),
+        Command::Completions { shell } => completion::print_script(*shell),
+        Command::Complete { kind } => completion::complete(config, *kind),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Command needs the master")),
+    }
+}
+
+fn handle(client: &mut Client, config: &Config, command: &Command) -> Result<(), std::io::Error> {
+    let editor = config.get_client().get_editor();
+    let master = config.get_master();
+    let fetch_url = master.get_fetch_url();
+    match command {
+        Command::Pkgb(PkgbCommand::Checkout { name, force }) => client.checkout(name, *force),
+        Command::Pkgb(PkgbCommand::Submit { path }) => client.submit(path),
+        Command::Pkgb(PkgbCommand::New(args)) => client.new_pkgbuild(
+            &args.name,
+            &editor,
+            &load_templates(&config.get_template_dir(), &config.get_templates().get_templates())?,
+            NewOptions {
+                template: args.template.clone(),
+                values: args.set.iter().cloned().collect(),
+                version: args.version.clone(),
+                source: args.source.clone(),
+                description: args.description.clone(),
+                dependencies: args.deps.clone(),
+                build_dependencies: args.build_deps.clone(),
+                edit: !args.no_edit,
+                submit: args.submit_now,
+                force: args.force,
+            },
+        ),
+        Command::Pkgb(PkgbCommand::Edit { name }) => client.edit(name, &editor),
+        Command::Pkgb(PkgbCommand::EditLocal { path }) => client.edit_local(path, &editor),
+        Command::Pkgb(PkgbCommand::Diff { path }) => client.diff_pkgb(path),
+        Command::Pkgb(PkgbCommand::Bump { name, version, rebuild }) => client.bump(name, version, config.get_bump().get_real_version(), *rebuild),
+        Command::Pkgb(PkgbCommand::List) => client.show_managed_pkgbs(),
+        Command::Pkgb(PkgbCommand::Export { force, bundle: Some(bundle), with_extrasources }) => client.export_bundle(&master, bundle, *with_extrasources, *force),
+        Command::Pkgb(PkgbCommand::Export { force, bundle: None, .. }) => client.export(*force),
+        Command::Pkgb(PkgbCommand::Import { bundle: Some(bundle), .. }) => client.import_bundle(&fetch_url, bundle),
+        Command::Pkgb(PkgbCommand::Import { path: Some(path), bundle: None }) => client.import(path),
+        Command::Pkgb(PkgbCommand::Import { path: None, bundle: None }) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Import needs a path or --bundle")),
+        Command::Pkg(PkgCommand::Build { name, cross }) => client.build(name, !cross),
+        Command::Pkg(PkgCommand::Fetch { name, output, verify, with_deps: true }) => client.get_pkg_with_deps(&master, &config.get_cache(), name, output.as_deref(), *verify),
+        Command::Pkg(PkgCommand::Fetch { name, output, verify, with_deps: false }) => client.get_pkg(&master, &config.get_cache(), name, output.as_deref(), *verify),
+        Command::Pkg(PkgCommand::Inspect { target }) => client.inspect_pkg(&master, &config.get_cache(), target),
+        Command::Pkg(PkgCommand::Find { name }) => client.get_pkg_with_name(name),
+        Command::Pkg(PkgCommand::Info { name }) => client.get_info(name),
+        Command::Pkg(PkgCommand::Remove { name }) => client.remove_pkg(name),
+        Command::Pkg(PkgCommand::List) => client.show_managed_pkgs(),
+        Command::Pkg(PkgCommand::Diff) => client.show_diff(),
+        Command::Pkg(PkgCommand::Dependers { name }) => client.show_dependers(name),
+        Command::Pkg(PkgCommand::Dependencies { name }) => client.show_dependencies(name),
+        Command::Pkg(PkgCommand::RebuildDependers { name }) => client.rebuild_dependers(name),
+        Command::Pkg(PkgCommand::RebuildDependencies { name, kind }) => client.rebuild_dependencies(name, kind.into()),
+        Command::Job(JobCommand::Status) => client.show_jobs_status(false),
+        Command::Job(JobCommand::Watch { interval }) => client.watch_jobs(interval),
+        Command::Job(JobCommand::Log { id }) => client.watch_job_log(id, 1),
+        Command::Job(JobCommand::Latest { running: false }) => client.show_latest_complete_log(),
+        Command::Job(JobCommand::Latest { running: true }) => client.show_latest_running_log(),
+        Command::Job(JobCommand::SysLog) => client.show_sys_log(),
+        Command::Job(JobCommand::ClearCompleted) => client.clear_completed(),
+        Command::Job(JobCommand::Cancel { id, .. }) => client.cancel_queued(id.as_deref()),
+        Command::Job(JobCommand::Solution { path, cross }) => client.submit_solution(path, !cross),
+        Command::Client(ClientCommand::Status) => client.show_clients(),
+        Command::Client(ClientCommand::Info { name }) => client.show_client_info(name),
+        Command::ExtraSource(ExtraSourceCommand::List) => client.show_extra_sources(),
+        Command::ExtraSource(ExtraSourceCommand::Unused) => client.show_unused_extra_sources(),
+        Command::ExtraSource(ExtraSourceCommand::Prune) => client.prune_extra_sources(),
+        Command::ExtraSource(ExtraSourceCommand::Remove { id }) => client.remove_extra_source(id),
+        Command::ExtraSource(ExtraSourceCommand::Submit { path, description }) => client.submit_extra_source(path, description.as_deref()),
+        Command::ExtraSource(ExtraSourceCommand::SubmitAll { path }) => client.submit_extra_sources(&fetch_url, path),
+        Command::ExtraSource(ExtraSourceCommand::Fetch { id }) => client.fetch_extra_source(&fetch_url, id),
+        Command::ExtraSource(ExtraSourceCommand::FetchFor { name }) => client.fetch_extra_sources_for(&fetch_url, name),
+        Command::Sync(SyncCommand::Pull { repo, force }) => client.sync_pull(&master, &config.get_master_name(), repo, *force),
+        Command::Sync(SyncCommand::Push { repo, force }) => client.sync_push(&master, repo, *force),
+        Command::Shell => shell::run(client, config, handle),
+        Command::Batch { file, continue_on_error } => batch::run(client, config, handle, file, *continue_on_error),
+        command => handle_local(config, command),
+    }
+}
@@ -0,0 +1,53 @@
+use serde_derive::{Deserialize, Serialize};
+
+// version of the bundle layout, bumped on incompatible changes
+pub const BUNDLE_VERSION: u32 = 1;
+// path of the manifest inside a bundle, pkgbuilds are under pkgbuilds/ and extrasources under extrasources/<id>/
+pub const MANIFEST: &str = "manifest.toml";
+
+// lists everything in a bundle with its checksum
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct Manifest {
+    pub version: u32,
+    // addr:port of the master the bundle was exported from
+    pub master: String,
+    pub fetch_url: String,
+    // seconds since the unix epoch
+    pub created: u64,
+    pub pkgbuild: Vec<BundledPkgbuild>,
+    pub extrasource: Vec<BundledExtraSource>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BundledPkgbuild {
+    pub name: String,
+    pub file: String,
+    pub sha256: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BundledExtraSource {
+    // id on the exporting master, imports map it to the id on the new one
+    pub id: String,
+    pub filename: String,
+    pub description: String,
+    pub file: String,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn from_str(content: &str) -> Result<Self, std::io::Error> {
+        let manifest = toml::from_str::<Self>(content).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse bundle manifest: {err}")))?;
+        if manifest.version != BUNDLE_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Bundle version {} is not supported, expected {BUNDLE_VERSION}", manifest.version),
+            ));
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_string(&self) -> Result<String, std::io::Error> {
+        toml::to_string(self).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to serialize bundle manifest: {err}")))
+    }
+}
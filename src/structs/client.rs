@@ -1,23 +1,92 @@
-use std::{io::Write, net::TcpStream};
+use std::{
+    io::Write,
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
 
+use console::Style;
 use log::trace;
 
+use crate::json::{auth::AuthRequest, request::Request};
+
+// commands changing state on the master, printed instead of sent in dry run mode
+const MUTATING: &[&str] = &[
+    "SUBMIT",
+    "BUILD",
+    "REBUILDDEPENDERS",
+    "DELETEPKG",
+    "CANCELQUEUEDJOB",
+    "CANCELQUEUEDJOBS",
+    "CLEARCOMPLETEDJOBS",
+    "SUBMITSOLUTION",
+    "REMOVEEXTRASOURCE",
+    "TRANSFEREXTRASOURCE",
+];
+
 pub struct Client {
     pub socket: TcpStream,
+    // mutating requests are printed and answered with DRY_RUN instead of being sent
+    pub dry_run: bool,
+    addr: String,
+    port: u16,
+    // set by a successful auth, used to open more connections
+    pub login: Option<AuthRequest>,
 }
 
 impl Client {
     pub fn new(addr: &str, port: u16) -> Result<Self, std::io::Error> {
         Ok(Self {
             socket: TcpStream::connect(format!("{}:{}", addr, port))?,
+            dry_run: false,
+            addr: addr.to_owned(),
+            port,
+            login: None,
         })
     }
 
+    // like new, but gives up connecting after timeout and fails reads and writes taking longer
+    pub fn with_timeout(addr: &str, port: u16, timeout: Duration) -> Result<Self, std::io::Error> {
+        let mut last = std::io::Error::new(std::io::ErrorKind::NotFound, format!("{addr} does not resolve to any address"));
+        for address in (addr, port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, timeout) {
+                Ok(socket) => {
+                    socket.set_read_timeout(Some(timeout))?;
+                    socket.set_write_timeout(Some(timeout))?;
+                    return Ok(Self {
+                        socket,
+                        dry_run: false,
+                        addr: addr.to_owned(),
+                        port,
+                        login: None,
+                    });
+                }
+                Err(err) => last = err,
+            }
+        }
+        Err(last)
+    }
+
+    // opens another connection to the same master, authenticated like this one
+    pub fn connect_another(&self) -> Result<Client, std::io::Error> {
+        let Some(login) = &self.login else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Not authenticated"));
+        };
+        let mut client = Client::new(&self.addr, self.port)?;
+        client.auth(&login.machine_identifier, &login.machine_type, &login.machine_authkey, login.machine_version)?;
+        client.dry_run = self.dry_run;
+        Ok(client)
+    }
+
     pub fn write_read(&mut self, content: &str) -> Result<String, std::io::Error> {
+        if self.dry_run && is_mutating(content) {
+            println!("{} {content}", Style::new().yellow().bold().apply_to("Dry run, not sent:"));
+            return Ok(serde_json::json!({ "statuscode": 200, "payload": "DRY_RUN" }).to_string());
+        }
         self.write(content)?;
         self.read()
     }
 
+    #[allow(clippy::needless_as_bytes)]
     pub fn write(&mut self, content: &str) -> Result<(), std::io::Error> {
         let len = content.bytes().len();
         let msg = format!("{len} {content}");
@@ -64,3 +133,7 @@ impl Client {
         self.socket.shutdown(std::net::Shutdown::Both)
     }
 }
+
+fn is_mutating(content: &str) -> bool {
+    serde_json::from_str::<Request>(content).is_ok_and(|request| MUTATING.contains(&request.command.as_str()))
+}
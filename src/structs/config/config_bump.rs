@@ -0,0 +1,39 @@
+use serde_derive::{Deserialize, Serialize};
+
+// what happens to real_version when a pkgbuild gets a new version
+pub enum RealVersionPolicy {
+    Reset,
+    Increment,
+    Keep,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Bump {
+    real_version: Option<String>,
+}
+
+impl Bump {
+    // unknown values are reported by validate
+    pub fn get_real_version(&self) -> RealVersionPolicy {
+        match self.real_version.clone().unwrap_or_default().as_str() {
+            "increment" => RealVersionPolicy::Increment,
+            "keep" => RealVersionPolicy::Keep,
+            _ => RealVersionPolicy::Reset,
+        }
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        match self.real_version.as_deref() {
+            None | Some("reset" | "increment" | "keep") => Vec::new(),
+            Some(policy) => vec![format!("bump.real_version {policy} is not valid, expected reset, increment or keep")],
+        }
+    }
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Self {
+            real_version: Some("reset".to_owned()),
+        }
+    }
+}
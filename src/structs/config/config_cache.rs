@@ -0,0 +1,34 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::util::funcs::expand_home;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Cache {
+    dir: Option<String>,
+    max_size_mb: Option<u64>,
+    mirror: Option<String>,
+}
+
+impl Cache {
+    pub fn get_dir(&self) -> String {
+        self.dir.as_deref().map(expand_home).unwrap_or(format!("{}/rranch", dirs::cache_dir().unwrap_or_default().to_str().unwrap_or_default()))
+    }
+
+    pub fn get_max_size_mb(&self) -> u64 {
+        self.max_size_mb.unwrap_or(2048)
+    }
+
+    pub fn get_mirror(&self) -> Option<String> {
+        self.mirror.as_deref().map(expand_home)
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            dir: Some(format!("{}/rranch", dirs::cache_dir().unwrap_or_default().to_str().unwrap_or_default())),
+            max_size_mb: Some(2048),
+            mirror: None,
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use serde_derive::{Deserialize, Serialize};
 
+use crate::util::funcs::find_in_path;
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Client {
     name: Option<String>,
@@ -21,6 +23,17 @@ impl Client {
         }
     }
 
+    // returns a copy with every field set in other replacing the own one
+    pub fn overlay(&self, other: &Client) -> Client {
+        Self {
+            name: other.name.clone().or(self.name.clone()),
+            r#type: other.r#type.clone().or(self.r#type.clone()),
+            loglevel: other.loglevel.clone().or(self.loglevel.clone()),
+            editor: other.editor.clone().or(self.editor.clone()),
+            protver: other.protver.or(self.protver),
+        }
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone().unwrap_or("a-rranch-client".to_owned())
     }
@@ -37,6 +50,21 @@ impl Client {
         self.editor.clone().unwrap_or("vim".to_owned())
     }
 
+    // returns a description of every problem, checking the editor only if it is going to be used
+    pub fn validate(&self, editor: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !["CONTROLLER", "BUILD"].contains(&self.get_type().as_str()) {
+            problems.push(format!("client.type {} is not valid, expected CONTROLLER or BUILD", self.get_type()));
+        }
+        if !["ERROR", "WARN", "INFO", "DEBUG", "TRACE", "NONE"].contains(&self.get_loglevel().to_uppercase().as_str()) {
+            problems.push(format!("client.loglevel {} is not valid, expected one of ERROR, WARN, INFO, DEBUG, TRACE or NONE", self.get_loglevel()));
+        }
+        if editor && !find_in_path(&self.get_editor()) {
+            problems.push(format!("client.editor {} was not found on PATH", self.get_editor()));
+        }
+        problems
+    }
+
     pub fn get_protver(&self) -> u16 {
         self.protver.unwrap_or_default()
     }
@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use toml::{Table, Value};
+
+// where an effective config value came from
+#[derive(Debug, Clone)]
+pub enum Origin {
+    Default,
+    File(String),
+    // a .rranch.toml found from the working directory upwards
+    Project(String),
+    Profile(String),
+    Env(String),
+    Flag(String),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) | Origin::Project(path) => write!(f, "{path}"),
+            Origin::Profile(name) => write!(f, "profile {name}"),
+            Origin::Env(var) => write!(f, "env {var}"),
+            Origin::Flag(flag) => write!(f, "flag {flag}"),
+        }
+    }
+}
+
+impl Origin {
+    // a project config comes with whatever directory rranch runs in, every other layer is set by the user or root
+    pub fn is_trusted(&self) -> bool {
+        !matches!(self, Origin::Project(_))
+    }
+}
+
+pub enum Kind {
+    Str,
+    Int,
+    Bool,
+}
+
+// a config key that can be overridden through an RRANCH_* env var and a command line flag
+pub struct Override {
+    pub key: &'static str,
+    pub short: &'static str,
+    pub long: &'static str,
+    kind: Kind,
+}
+
+pub const OVERRIDES: &[Override] = &[
+    Override::new("master.addr", "ma", "master-addr", Kind::Str),
+    Override::new("master.port", "mp", "master-port", Kind::Int),
+    Override::new("master.authkey", "ak", "authkey", Kind::Str),
+    Override::new("master.authkey_file", "akf", "authkey-file", Kind::Str),
+    Override::new("master.authkey_command", "akc", "authkey-command", Kind::Str),
+    Override::new("master.fetch_url", "fu", "fetch-url", Kind::Str),
+    Override::new("master.mirror_layout", "ml", "mirror-layout", Kind::Bool),
+    Override::new("client.name", "cn", "client-name", Kind::Str),
+    Override::new("client.type", "ct", "client-type", Kind::Str),
+    Override::new("client.loglevel", "ll", "loglevel", Kind::Str),
+    Override::new("client.editor", "ed", "editor", Kind::Str),
+    Override::new("cache.dir", "cd", "cache-dir", Kind::Str),
+    Override::new("cache.max_size_mb", "cms", "cache-max-size", Kind::Int),
+    Override::new("cache.mirror", "cm", "cache-mirror", Kind::Str),
+    Override::new("bump.real_version", "brv", "bump-real-version", Kind::Str),
+];
+
+impl Override {
+    const fn new(key: &'static str, short: &'static str, long: &'static str, kind: Kind) -> Self {
+        Self { key, short, long, kind }
+    }
+
+    // eg. RRANCH_MASTER_ADDR for --master-addr
+    pub fn get_env(&self) -> String {
+        format!("RRANCH_{}", self.long.to_uppercase().replace('-', "_"))
+    }
+
+    pub fn get_param(&self) -> &'static str {
+        match self.kind {
+            Kind::Str => "value",
+            Kind::Int => "number",
+            Kind::Bool => "true|false",
+        }
+    }
+
+    pub fn parse(&self, value: &str) -> Result<Value, std::io::Error> {
+        let invalid = |err: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid value {value} for {}: {err}", self.key));
+        match self.kind {
+            Kind::Str => Ok(Value::String(value.to_owned())),
+            Kind::Int => value.parse::<i64>().map(Value::Integer).map_err(|err| invalid(err.to_string())),
+            Kind::Bool => value.parse::<bool>().map(Value::Boolean).map_err(|err| invalid(err.to_string())),
+        }
+    }
+}
+
+// keys that are alternatives to each other. a layer setting one of them drops the others set by lower layers
+const EXCLUSIVE: &[&[&str]] = &[&["master.authkey", "master.authkey_file", "master.authkey_command"]];
+
+// toml tables merged on top of each other key by key, remembering which layer set each value
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    table: Table,
+    origins: BTreeMap<String, Origin>,
+}
+
+impl Layers {
+    pub fn merge(&mut self, table: &Table, origin: &Origin) {
+        merge_into(&mut self.table, &mut self.origins, "", table, origin)
+    }
+
+    // sets a single dotted key, eg. master.addr
+    pub fn set(&mut self, key: &str, value: Value, origin: Origin) {
+        let mut table = Table::new();
+        let mut parts = key.rsplit('.');
+        table.insert(parts.next().unwrap_or_default().to_owned(), value);
+        for part in parts {
+            let mut parent = Table::new();
+            parent.insert(part.to_owned(), Value::Table(table));
+            table = parent;
+        }
+        self.merge(&table, &origin)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let mut parts = key.split('.');
+        let mut value = self.table.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+        Some(value)
+    }
+
+    pub fn get_origin(&self, key: &str) -> Option<&Origin> {
+        self.origins.get(key)
+    }
+
+    pub fn get_table(&self) -> Table {
+        self.table.clone()
+    }
+
+    // every leaf value with its dotted key and origin, sorted by key
+    pub fn get_values(&self) -> Vec<(String, Value, Origin)> {
+        self.origins
+            .iter()
+            .filter_map(|(key, origin)| self.get(key).map(|value| (key.clone(), value.clone(), origin.clone())))
+            .collect()
+    }
+}
+
+fn merge_into(target: &mut Table, origins: &mut BTreeMap<String, Origin>, prefix: &str, table: &Table, origin: &Origin) {
+    let path_of = |key: &str| if prefix.is_empty() { key.to_owned() } else { format!("{prefix}.{key}") };
+
+    for group in EXCLUSIVE {
+        if !table.keys().any(|key| group.contains(&path_of(key).as_str())) {
+            continue;
+        }
+        for path in group.iter().filter(|path| !table.keys().any(|key| path_of(key) == **path)) {
+            if let Some(key) = path.strip_prefix(&path_of("")) {
+                target.remove(key);
+                origins.remove(*path);
+            }
+        }
+    }
+
+    for (key, value) in table {
+        let path = path_of(key);
+        match value {
+            Value::Table(child) => {
+                if !matches!(target.get(key), Some(Value::Table(_))) {
+                    origins.remove(&path);
+                    target.insert(key.clone(), Value::Table(Table::new()));
+                }
+                if let Some(Value::Table(existing)) = target.get_mut(key) {
+                    merge_into(existing, origins, &path, child, origin);
+                }
+            }
+            value => {
+                // a value replacing a whole table also replaces the origins of its keys
+                origins.retain(|existing, _| !existing.starts_with(&format!("{path}.")));
+                origins.insert(path, origin.clone());
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
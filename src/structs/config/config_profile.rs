@@ -0,0 +1,16 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::config_master::Master;
+
+// a named master, eg. [profiles.staging]. its client overrides are merged into [client] before parsing
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Profile {
+    #[serde(flatten)]
+    master: Master,
+}
+
+impl Profile {
+    pub fn get_master(&self) -> Master {
+        self.master.clone()
+    }
+}
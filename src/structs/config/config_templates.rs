@@ -36,6 +36,32 @@ impl Default for Templates {
                 String::from("\tDESTDIR=$PKG_INSTALL_DIR ninja install"),
             ],
         );
+        map.insert(
+            String::from("meson"),
+            vec![
+                String::from("\tcd $PKG_NAME-$PKG_VERSION"),
+                String::from("\t"),
+                String::from("\tmeson setup build --prefix=/usr --buildtype=release"),
+                String::from("\tDESTDIR=$PKG_INSTALL_DIR ninja -C build install"),
+            ],
+        );
+        map.insert(
+            String::from("cmake"),
+            vec![
+                String::from("\tcd $PKG_NAME-$PKG_VERSION"),
+                String::from("\t"),
+                String::from("\tcmake -B build -G Ninja -DCMAKE_INSTALL_PREFIX=/usr -DCMAKE_BUILD_TYPE=Release"),
+                String::from("\tDESTDIR=$PKG_INSTALL_DIR ninja -C build install"),
+            ],
+        );
+        map.insert(
+            String::from("cargo"),
+            vec![
+                String::from("\tcd $PKG_NAME-$PKG_VERSION"),
+                String::from("\t"),
+                String::from("\tcargo install --locked --no-track --path . --root $PKG_INSTALL_DIR/usr"),
+            ],
+        );
         Self { templates: Some(map) }
     }
 }
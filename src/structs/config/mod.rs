@@ -1,4 +1,8 @@
+pub mod config_bump;
+pub mod config_cache;
 pub mod config_client;
+pub mod config_layers;
 pub mod config_main;
 pub mod config_master;
+pub mod config_profile;
 pub mod config_templates;
@@ -0,0 +1,32 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct Lockfile {
+    pub fetch_url: String,
+    pub package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub real_version: String,
+    pub file: String,
+    pub sha256: String,
+}
+
+impl Lockfile {
+    pub fn new(fetch_url: &str) -> Self {
+        Self {
+            fetch_url: fetch_url.to_owned(),
+            package: Vec::new(),
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), std::io::Error> {
+        match toml::to_string(self) {
+            Ok(content) => std::fs::write(path, content),
+            Err(err) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to serialize lockfile: {err}"))),
+        }
+    }
+}
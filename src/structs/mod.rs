@@ -1,4 +1,9 @@
+pub mod bundle;
 pub mod client;
 pub mod config;
 pub mod deps;
 pub mod diff;
+pub mod lockfile;
+pub mod sync_state;
+pub mod template;
+pub mod transfer;
@@ -0,0 +1,32 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+// what the master and a git repo agreed on at the last sync, kept in the repo's .git directory
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncState {
+    // addr:port of the master the hashes belong to
+    pub master: String,
+    // HEAD after the last push that submitted everything, later changes are pushed next time
+    pub commit: Option<String>,
+    // content hash of every pkgbuild as of the last sync
+    pub pkgbuilds: BTreeMap<String, String>,
+}
+
+impl SyncState {
+    // a missing file is an empty state, eg. before the first sync
+    pub fn load(path: &str) -> Result<Self, std::io::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse sync state {path}: {err}"))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), std::io::Error> {
+        match toml::to_string(self) {
+            Ok(content) => std::fs::write(path, content),
+            Err(err) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to serialize sync state: {err}"))),
+        }
+    }
+}
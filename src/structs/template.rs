@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use console::Style;
+use log::{trace, warn};
+
+use crate::json::pkgbuild::PackageBuild;
+
+// what -n fills in from flags instead of asking
+#[derive(Debug, Default)]
+pub struct NewOptions {
+    pub template: Option<String>,
+    pub values: HashMap<String, String>,
+    pub version: Option<String>,
+    pub source: Option<String>,
+    pub description: Option<String>,
+    pub dependencies: Vec<String>,
+    pub build_dependencies: Vec<String>,
+    // open the editor, which also asks whether to submit
+    pub edit: bool,
+    // submit without asking, only used without the editor
+    pub submit: bool,
+    // overwrite an existing local pkgbuild without asking
+    pub force: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum TemplateKind {
+    // a whole pkgbuild skeleton from the template directory
+    File(PathBuf),
+    // build script lines from [templates] in the config
+    BuildScript,
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    pub description: String,
+    pub kind: TemplateKind,
+    content: String,
+}
+
+impl Template {
+    // the leading # lines of a template file are its description and are not part of the pkgbuild
+    pub fn from_file(path: &Path) -> Result<Self, std::io::Error> {
+        let file = std::fs::read_to_string(path)?;
+        let header = file.lines().take_while(|line| line.starts_with('#')).collect::<Vec<&str>>();
+        let description = header
+            .iter()
+            .map(|line| line.trim_start_matches('#').trim())
+            .map(|line| line.strip_prefix("description:").unwrap_or(line).trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_owned();
+
+        Ok(Self {
+            name: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            description,
+            kind: TemplateKind::File(path.to_owned()),
+            content: file.lines().skip(header.len()).collect::<Vec<&str>>().join("\n"),
+        })
+    }
+
+    pub fn from_build_script(name: &str, lines: &[String]) -> Self {
+        Self {
+            name: name.to_owned(),
+            description: "build script from config".to_owned(),
+            kind: TemplateKind::BuildScript,
+            content: lines.join("\n"),
+        }
+    }
+
+    // names of all {{placeholders}} in order of appearance
+    pub fn get_variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        let mut rest = self.content.as_str();
+        while let Some((_, after)) = rest.split_once("{{") {
+            let Some((variable, after)) = after.split_once("}}") else {
+                break;
+            };
+            let variable = variable.trim().to_owned();
+            if !variable.is_empty() && !variables.contains(&variable) {
+                variables.push(variable);
+            }
+            rest = after;
+        }
+        variables
+    }
+
+    // replaces every placeholder with its value and parses the result
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<PackageBuild, std::io::Error> {
+        let mut content = self.content.clone();
+        for variable in self.get_variables() {
+            let value = match values.get(&variable) {
+                Some(value) => value,
+                None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("No value for {{{{{variable}}}}}"))),
+            };
+            content = replace_placeholder(&content, &variable, value);
+        }
+
+        match self.kind {
+            TemplateKind::File(_) => PackageBuild::from_str(&content),
+            TemplateKind::BuildScript => {
+                let mut pkgb = PackageBuild::new();
+                pkgb.build_script = content.lines().map(str::to_owned).collect();
+                Ok(pkgb)
+            }
+        }
+    }
+}
+
+// placeholders may contain whitespace around the name, eg. {{ version }}
+fn replace_placeholder(content: &str, variable: &str, value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = content;
+    while let Some((before, after)) = rest.split_once("{{") {
+        match after.split_once("}}") {
+            Some((name, after)) if name.trim() == variable => {
+                out.push_str(before);
+                out.push_str(value);
+                rest = after;
+            }
+            _ => {
+                out.push_str(before);
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// templates from the template directory followed by the ones from the config, sorted by name.
+// a file shadows a config template with the same name
+pub fn load_templates(dir: &Path, build_scripts: &HashMap<String, Vec<String>>) -> Result<Vec<Template>, std::io::Error> {
+    let mut templates = Vec::new();
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("bpb") {
+                continue;
+            }
+            match Template::from_file(&path) {
+                Ok(template) => templates.push(template),
+                Err(err) => warn!("Failed to read template {}: {}", path.display(), err),
+            }
+        }
+    } else {
+        trace!("Template directory {} does not exist", dir.display());
+    }
+
+    for (name, lines) in build_scripts {
+        if !templates.iter().any(|template| &template.name == name) {
+            templates.push(Template::from_build_script(name, lines));
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub fn show_templates(templates: &[Template]) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    let italic = Style::new().italic();
+
+    println!("{}", bold.apply_to("Templates"));
+    println!("{}", italic.apply_to(format!("{:<20} {:<40} {:<30} {}", "NAME", "DESCRIPTION", "VARIABLES", "SOURCE")));
+    for template in templates {
+        let source = match &template.kind {
+            TemplateKind::File(path) => path.display().to_string(),
+            TemplateKind::BuildScript => "config".to_owned(),
+        };
+        println!("{:<20} {:<40} {:<30} {}", template.name, template.description, template.get_variables().join(", "), source);
+    }
+    Ok(())
+}
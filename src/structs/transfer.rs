@@ -0,0 +1,47 @@
+use console::Style;
+
+// what an export or import did with each pkgbuild
+#[derive(Default, Debug)]
+pub struct TransferSummary {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    // changed, but left alone on request
+    pub skipped: Vec<String>,
+    // name and reason
+    pub failed: Vec<(String, String)>,
+}
+
+impl TransferSummary {
+    pub fn print(&self) {
+        let bold = Style::new().bold();
+        let groups = [
+            ("Added", &self.added, Style::new().green()),
+            ("Updated", &self.updated, Style::new().yellow()),
+            ("Unchanged", &self.unchanged, Style::new()),
+            ("Skipped", &self.skipped, Style::new().dim()),
+        ];
+        for (title, names, style) in groups {
+            if !names.is_empty() {
+                println!("{} {}", bold.apply_to(format!("{title} ({}):", names.len())), style.apply_to(names.join(", ")));
+            }
+        }
+        if !self.failed.is_empty() {
+            println!("{}", bold.apply_to(format!("Failed ({}):", self.failed.len())));
+            for (name, reason) in &self.failed {
+                println!("  {} {reason}", Style::new().red().apply_to(format!("{name}:")));
+            }
+        }
+    }
+
+    // an error naming the failed pkgbuilds, if there are any
+    pub fn check(&self, action: &str) -> Result<(), std::io::Error> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        Err(std::io::Error::other(format!(
+            "Failed to {action} {}",
+            self.failed.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>().join(", ")
+        )))
+    }
+}
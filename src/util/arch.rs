@@ -0,0 +1,866 @@
+use std::collections::HashMap;
+
+use console::Style;
+use log::warn;
+
+use crate::json::pkgbuild::PackageBuild;
+
+// run by makepkg in this order, each starting in $srcdir
+const FUNCTIONS: &[&str] = &["prepare", "build", "check", "package"];
+// variables makepkg sets for the functions and their name in a build script
+const VARIABLES: &[(&str, &str)] = &[("pkgdir", "PKG_INSTALL_DIR"), ("srcdir", "SRC_DIR"), ("pkgname", "PKG_NAME"), ("pkgver", "PKG_VERSION")];
+// set by makepkg without an equivalent
+const UNSUPPORTED_VARIABLES: &[&str] = &["startdir", "pkgbase", "CARCH", "CHOST"];
+// metadata without an effect on the build
+const IGNORED: &[&str] = &["pkgbase", "pkgrel", "epoch", "arch", "url", "license", "groups", "validpgpkeys"];
+const SIGNATURES: &[&str] = &[".sig", ".asc", ".sign"];
+const VCS_PREFIXES: &[&str] = &["git+", "git://", "svn+", "hg+", "bzr+", "fossil+"];
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Scalar(String),
+    Array(Vec<String>),
+}
+
+struct Function {
+    name: String,
+    line: usize,
+    // with the line number of every body line
+    body: Vec<(usize, String)>,
+}
+
+// what a PKGBUILD sets at the top level, read without running bash
+#[derive(Default)]
+struct Recipe {
+    values: HashMap<String, (usize, Value)>,
+    functions: Vec<Function>,
+    // "line N: reason" for everything that was not translated
+    problems: Vec<String>,
+}
+
+// reads the Arch PKGBUILD at path and writes it as ./<name>/package.bpb, parts without an equivalent are reported
+pub fn convert(path: &str, force: bool) -> Result<(), std::io::Error> {
+    let mut recipe = parse(&std::fs::read_to_string(path)?);
+    let mut pkgb = to_pkgbuild(&mut recipe);
+    if pkgb.name.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path} sets no pkgname")));
+    }
+
+    recipe.problems.iter().for_each(|problem| warn!("{problem}"));
+    pkgb.create_workdir(force)?;
+    // create_workdir leaves an existing copy alone if the user declines
+    let written = std::fs::read_to_string(format!("{}/package.bpb", pkgb.name))?;
+    if !pkgb.is_same(&PackageBuild::from_str(&written)?)? {
+        return Ok(());
+    }
+
+    println!("{}", Style::new().bold().apply_to(format!("Converted {path} into {}/package.bpb", pkgb.name)));
+    if !recipe.problems.is_empty() {
+        warn!("{} parts of {path} were not translated, review {}/package.bpb before submitting it", recipe.problems.len(), pkgb.name);
+    }
+    Ok(())
+}
+
+fn parse(content: &str) -> Recipe {
+    let mut recipe = Recipe::default();
+    // plain variables are expanded in later values like bash would
+    let mut scalars: HashMap<String, String> = HashMap::new();
+    let lines = content.lines().collect::<Vec<&str>>();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let number = index + 1;
+        let line = lines[index].trim();
+        index += 1;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // function bodies end at the first } in the first column, like makepkg's own PKGBUILDs do. a here document may
+        // hold one as well, eg. a json file written with cat <<EOF
+        if let Some((name, mut opened)) = parse_function_header(line) {
+            let mut body = Vec::new();
+            let mut here_document: Option<(String, bool)> = None;
+            while index < lines.len() {
+                let line = lines[index];
+                index += 1;
+                if let Some((delimiter, strip_tabs)) = here_document.take() {
+                    let end = if strip_tabs { line.trim_start_matches('\t') } else { line };
+                    if end != delimiter {
+                        here_document = Some((delimiter, strip_tabs));
+                    }
+                    body.push((index, line.to_owned()));
+                } else if !opened && line.trim() == "{" {
+                    opened = true;
+                } else if line.starts_with('}') {
+                    break;
+                } else {
+                    here_document = parse_here_document(line).map(|(delimiter, strip_tabs, _)| (delimiter, strip_tabs));
+                    body.push((index, line.to_owned()));
+                }
+            }
+            recipe.functions.push(Function { name, line: number, body });
+            continue;
+        }
+
+        let Some((name, append, value)) = parse_assignment(line) else {
+            recipe.problems.push(format!("line {number}: `{line}` is not translated"));
+            continue;
+        };
+        let mut value = value.to_owned();
+        while !is_complete(&value) && index < lines.len() {
+            value = format!("{value}\n{}", lines[index]);
+            index += 1;
+        }
+        if value.contains("$(") || value.contains('`') {
+            recipe.problems.push(format!("line {number}: {name} runs a command, it is not translated"));
+            continue;
+        }
+
+        let value = match split_value(&value) {
+            Ok(value) => value,
+            Err(err) => {
+                recipe.problems.push(format!("line {number}: {name} is not translated, {err}"));
+                continue;
+            }
+        };
+        let mut expand = |word: &str| {
+            replace_variables(word, |variable, rest, _| match scalars.get(variable) {
+                Some(known) if rest.is_empty() => Some(known.clone()),
+                _ => {
+                    recipe.problems.push(format!("line {number}: ${variable} in {name} is not translated"));
+                    None
+                }
+            })
+        };
+        let value = match value {
+            Value::Scalar(word) => Value::Scalar(expand(&word)),
+            Value::Array(words) => Value::Array(words.iter().flat_map(|word| expand_braces(word)).map(|word| expand(&word)).collect()),
+        };
+
+        match (value, recipe.values.remove(&name)) {
+            (Value::Array(words), Some((line, Value::Array(known)))) if append => {
+                recipe.values.insert(name, (line, Value::Array([known, words].concat())));
+            }
+            (Value::Scalar(word), _) => {
+                scalars.insert(name.clone(), word.clone());
+                recipe.values.insert(name, (number, Value::Scalar(word)));
+            }
+            (value, _) => {
+                scalars.remove(&name);
+                recipe.values.insert(name, (number, value));
+            }
+        }
+    }
+    recipe
+}
+
+fn to_pkgbuild(recipe: &mut Recipe) -> PackageBuild {
+    let mut pkgb = PackageBuild::new();
+    let mut problems = Vec::new();
+    let mut sums = Vec::new();
+
+    let mut keys = recipe.values.keys().cloned().collect::<Vec<String>>();
+    keys.sort_by_key(|key| recipe.values[key].0);
+    for key in keys {
+        let (line, value) = &recipe.values[&key];
+        let words = match value {
+            Value::Scalar(word) => vec![word.clone()],
+            Value::Array(words) => words.clone(),
+        };
+        match key.as_str() {
+            "pkgname" => {
+                pkgb.name = words.first().cloned().unwrap_or_default();
+                if words.len() > 1 {
+                    problems.push(format!("line {line}: split packages are not supported, only {} was converted", pkgb.name));
+                }
+            }
+            // a pkgbuild holds one line per key
+            "pkgver" => pkgb.version = words.join(" ").split_whitespace().collect::<Vec<&str>>().join(" "),
+            "pkgdesc" => pkgb.description = words.join(" ").split_whitespace().collect::<Vec<&str>>().join(" "),
+            "depends" => pkgb.dependencies = strip_versions(*line, &words, &mut problems),
+            "makedepends" | "checkdepends" => pkgb.build_dependencies.extend(strip_versions(*line, &words, &mut problems)),
+            "source" => {
+                for entry in &words {
+                    let url = entry.split_once("::").map(|(_, url)| url).unwrap_or(entry);
+                    if SIGNATURES.iter().any(|extension| url.ends_with(extension)) {
+                        problems.push(format!("line {line}: signature {url} is not checked"));
+                    } else if VCS_PREFIXES.iter().any(|prefix| url.starts_with(prefix)) {
+                        problems.push(format!("line {line}: {url} is a VCS source, point source at a release tarball instead"));
+                    } else if !url.contains("://") {
+                        problems.push(format!("line {line}: {url} is a local file, submit it with `rranch es submit {url}` and add its id to extra_sources"));
+                    } else if pkgb.source.is_empty() {
+                        pkgb.source = url.to_owned();
+                    } else {
+                        problems.push(format!("line {line}: only one source is supported, {url} is not fetched"));
+                    }
+                }
+            }
+            key if key.ends_with("sums") || key.contains("sums_") => sums.push(key.to_owned()),
+            key if IGNORED.contains(&key) || key.starts_with('_') => (),
+            key => problems.push(format!("line {line}: {key} has no equivalent")),
+        }
+    }
+    if pkgb.name.is_empty() {
+        if let Some((_, Value::Scalar(pkgbase))) = recipe.values.get("pkgbase") {
+            pkgb.name = pkgbase.clone();
+        }
+    }
+    if pkgb.version.is_empty() {
+        problems.push("pkgver is not set".to_owned());
+    }
+    if !sums.is_empty() {
+        problems.push(format!("{} are not carried over, pkgbuilds have no checksums", sums.join(", ")));
+    }
+
+    pkgb.build_script = translate_functions(recipe, &mut problems);
+    recipe.problems.extend(problems);
+    pkgb
+}
+
+// every function becomes a subshell, so it starts in the source directory and fails on its first error like in makepkg
+fn translate_functions(recipe: &Recipe, problems: &mut Vec<String>) -> Vec<String> {
+    let scalars = recipe
+        .values
+        .iter()
+        .filter_map(|(name, (_, value))| match value {
+            Value::Scalar(word) => Some((name.as_str(), word.as_str())),
+            Value::Array(_) => None,
+        })
+        .collect::<HashMap<&str, &str>>();
+
+    for function in recipe.functions.iter().filter(|function| !FUNCTIONS.contains(&function.name.as_str())) {
+        match function.name.as_str() {
+            "pkgver" => problems.push(format!("line {}: pkgver() is not run, the version is the static pkgver", function.line)),
+            name if name.starts_with("package_") => problems.push(format!("line {}: split package function {name}() is not translated", function.line)),
+            name => problems.push(format!("line {}: helper function {name}() is not translated, inline it where it is called", function.line)),
+        }
+    }
+
+    let mut script = Vec::new();
+    let mut uses_srcdir = false;
+    for name in FUNCTIONS {
+        let Some(function) = recipe.functions.iter().find(|function| function.name == *name) else {
+            continue;
+        };
+        // here documents may start in the first column, so the first line sets the indentation
+        let indent = function
+            .body
+            .iter()
+            .find(|(_, line)| !line.trim().is_empty())
+            .map(|(_, line)| line.len() - line.trim_start().len())
+            .unwrap_or_default();
+
+        script.push(format!("\t# {name}()"));
+        script.push("\t(".to_owned());
+        script.push("\t\tset -e".to_owned());
+        let mut here_document: Option<(String, bool, bool)> = None;
+        let mut quoting = Quoting::default();
+        for (number, line) in &function.body {
+            // here document bodies and their delimiter are kept as written, their whitespace is content
+            let (indent_with, parts) = match here_document.take() {
+                Some((delimiter, strip_tabs, expands)) => {
+                    let end = if strip_tabs { line.trim_start_matches('\t') } else { line.as_str() };
+                    if end != delimiter {
+                        here_document = Some((delimiter, strip_tabs, expands));
+                    }
+                    if !expands {
+                        script.push(line.clone());
+                        continue;
+                    }
+                    // quotes are text in the body, variables in it are expanded either way
+                    ("", vec![(true, line.clone())])
+                }
+                None => {
+                    if line.trim().is_empty() {
+                        script.push(String::new());
+                        continue;
+                    }
+                    here_document = parse_here_document(line);
+                    let mut line = line[indent.min(line.len() - line.trim_start().len())..].to_owned();
+                    // the subshell scopes variables like the function did
+                    if let Some(assignment) = line.trim_start().strip_prefix("local ") {
+                        line = format!("{}{assignment}", &line[..line.len() - line.trim_start().len()]);
+                    }
+                    if line.trim_start().starts_with("return") {
+                        problems.push(format!("line {number}: return only works in functions, use exit"));
+                    }
+                    // bash keeps single quoted strings as written, eg. sed -i 's/$pkgver/…/'
+                    let parts = split_expanded(&line, &mut quoting).into_iter().map(|(expanded, part)| (expanded, part.to_owned())).collect();
+                    ("\t\t", parts)
+                }
+            };
+
+            let mut translate = |text: &str| {
+                replace_variables(text, |variable, rest, braced| {
+                    if let Some((_, to)) = VARIABLES.iter().find(|(from, _)| *from == variable) {
+                        uses_srcdir |= variable == "srcdir";
+                        return Some(if braced { format!("${{{to}{rest}}}") } else { format!("${to}") });
+                    }
+                    if UNSUPPORTED_VARIABLES.contains(&variable) {
+                        problems.push(format!("line {number}: ${variable} has no equivalent"));
+                        return None;
+                    }
+                    match (recipe.values.get(variable), scalars.get(variable)) {
+                        (Some(_), Some(known)) if rest.is_empty() => Some(known.to_string()),
+                        (Some(_), _) => {
+                            problems.push(format!("line {number}: ${{{variable}{rest}}} is not translated"));
+                            None
+                        }
+                        // set in the function itself or by the environment
+                        (None, _) => None,
+                    }
+                })
+            };
+            let line = parts.iter().map(|(expanded, part)| if *expanded { translate(part) } else { part.clone() }).collect::<String>();
+            script.push(format!("{indent_with}{line}"));
+        }
+        script.push("\t) || exit 1".to_owned());
+    }
+
+    // the build script starts where the sources are unpacked, like the functions start in $srcdir
+    if uses_srcdir {
+        script.insert(0, "\tSRC_DIR=$(pwd)".to_owned());
+    }
+    script
+}
+
+// quotes left open by the lines before, single quoted strings may span lines, eg. an awk script
+#[derive(Default)]
+struct Quoting {
+    single: bool,
+    double: bool,
+}
+
+// splits a line of a function into parts bash expands variables in and parts it keeps as written, which are single quoted
+// strings and comments
+fn split_expanded<'a>(line: &'a str, quoting: &mut Quoting) -> Vec<(bool, &'a str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut chars = line.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        if quoting.single {
+            if c == '\'' {
+                quoting.single = false;
+                parts.push((false, &line[start..=pos]));
+                start = pos + 1;
+            }
+            continue;
+        }
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => quoting.double = !quoting.double,
+            '\'' if !quoting.double => {
+                parts.push((true, &line[start..pos]));
+                start = pos;
+                quoting.single = true;
+            }
+            '#' if !quoting.double && (pos == 0 || line[..pos].ends_with(char::is_whitespace)) => {
+                parts.push((true, &line[start..pos]));
+                parts.push((false, &line[pos..]));
+                return parts;
+            }
+            _ => {}
+        }
+    }
+    parts.push((!quoting.single, &line[start..]));
+    parts
+}
+
+// delimiter of a here document started on line, whether it strips leading tabs (<<-) and whether its body is
+// expanded, eg. `cat > "$pkgdir"/etc/foo.conf <<'EOF'`
+fn parse_here_document(line: &str) -> Option<(String, bool, bool)> {
+    let start = line.find("<<")?;
+    let (before, rest) = (&line[..start], &line[start + 2..]);
+    // <<< is a here string and << in $((...)) a shift
+    if rest.starts_with('<') || before.matches("((").count() > before.matches("))").count() {
+        return None;
+    }
+    let (strip_tabs, rest) = match rest.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let word = rest.trim_start().split(|c: char| c.is_whitespace() || ";|&)<>".contains(c)).next()?;
+    // quoting any part of the delimiter turns off expansion in the body
+    let delimiter = word.replace(['\'', '"', '\\'], "");
+    if delimiter.is_empty() {
+        return None;
+    }
+    let expands = delimiter == word;
+    Some((delimiter, strip_tabs, expands))
+}
+
+// name and whether the { is on the same line, eg. `build() {` or `function build`
+fn parse_function_header(line: &str) -> Option<(String, bool)> {
+    let (keyword, line) = match line.strip_prefix("function ") {
+        Some(line) => (true, line.trim_start()),
+        None => (false, line),
+    };
+    let end = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(line.len());
+    let (name, rest) = line.split_at(end);
+    // the parentheses are optional after the function keyword
+    let rest = match rest.trim_start().strip_prefix("()") {
+        Some(rest) => rest.trim(),
+        None if keyword => rest.trim(),
+        None => return None,
+    };
+    if name.is_empty() || !(rest.is_empty() || rest == "{") {
+        return None;
+    }
+    Some((name.to_owned(), rest == "{"))
+}
+
+// name, whether it appends (+=) and the value, eg. `depends=('zlib'`
+fn parse_assignment(line: &str) -> Option<(String, bool, &str)> {
+    let end = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let (name, rest) = line.split_at(end);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    match rest.strip_prefix("+=") {
+        Some(value) => Some((name.to_owned(), true, value)),
+        None => rest.strip_prefix('=').map(|value| (name.to_owned(), false, value)),
+    }
+}
+
+// removes comments and reports whether quotes and parentheses are closed
+fn scan(value: &str) -> (String, bool) {
+    let mut out = String::new();
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut chars = value.chars().peekable();
+    let mut previous = ' ';
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                out.push(c);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+                previous = c;
+                continue;
+            }
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, '#') if previous.is_whitespace() || previous == '(' => {
+                while chars.peek().is_some_and(|next| *next != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            (None, _) => (),
+        }
+        out.push(c);
+        previous = c;
+    }
+    (out, quote.is_none() && depth <= 0)
+}
+
+fn is_complete(value: &str) -> bool {
+    scan(value).1
+}
+
+fn split_value(value: &str) -> Result<Value, String> {
+    let value = scan(value).0;
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix('(') {
+        let inner = inner.strip_suffix(')').ok_or("the array is not closed")?;
+        return shell_words::split(inner).map(Value::Array).map_err(|err| err.to_string());
+    }
+    let words = shell_words::split(value).map_err(|err| err.to_string())?;
+    match words.as_slice() {
+        [] => Ok(Value::Scalar(String::new())),
+        [word] => Ok(Value::Scalar(word.clone())),
+        _ => Err("the value is not a single word".to_owned()),
+    }
+}
+
+// calls replace for every $name and ${name...} in text with the name, the rest inside the braces and whether it was braced.
+// expansions replace returns none for are kept as they are
+fn replace_variables(text: &str, mut replace: impl FnMut(&str, &str, bool) -> Option<String>) -> String {
+    let ident_len = |text: &str| match text.starts_with(|c: char| c.is_ascii_digit()) {
+        true => 0,
+        false => text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(text.len()),
+    };
+
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, inner, braced, len) = match after.strip_prefix('{').and_then(|braced| braced.find('}').map(|end| &braced[..end])) {
+            Some(inner) => (&inner[..ident_len(inner)], &inner[ident_len(inner)..], true, inner.len() + 2),
+            None => (&after[..ident_len(after)], "", false, ident_len(after)),
+        };
+        if name.is_empty() {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        match replace(name, inner, braced) {
+            Some(replaced) => out.push_str(&replaced),
+            None => out.push_str(&rest[pos..pos + 1 + len]),
+        }
+        rest = &rest[pos + 1 + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// expands brace lists like bash, eg. file.tar.gz{,.sig} becomes file.tar.gz and file.tar.gz.sig
+fn expand_braces(word: &str) -> Vec<String> {
+    // ${name} is a variable, not a list
+    let mut from = 0;
+    let start = loop {
+        match word[from..].find('{').map(|found| from + found) {
+            Some(found) if word[..found].ends_with('$') => from = found + 1,
+            Some(found) => break found,
+            None => return vec![word.to_owned()],
+        }
+    };
+    let Some(end) = word[start..].find('}').map(|end| start + end) else {
+        return vec![word.to_owned()];
+    };
+    let (prefix, list, suffix) = (&word[..start], &word[start + 1..end], &word[end + 1..]);
+    if !list.contains(',') {
+        return expand_braces(suffix).into_iter().map(|rest| format!("{prefix}{{{list}}}{rest}")).collect();
+    }
+    list.split(',').flat_map(|part| expand_braces(&format!("{prefix}{part}{suffix}"))).collect()
+}
+
+// branch dependencies are plain names, eg. zlib>=1.2 becomes zlib
+fn strip_versions(line: usize, deps: &[String], problems: &mut Vec<String>) -> Vec<String> {
+    deps.iter()
+        .map(|dep| {
+            let name = dep.split(['<', '>', '=']).next().unwrap_or(dep);
+            if name != dep {
+                problems.push(format!("line {line}: version constraint of {dep} is dropped"));
+            }
+            name.to_owned()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_braces, parse, parse_function_header, parse_here_document, replace_variables, split_value, to_pkgbuild, Value};
+
+    // core/which
+    const WHICH: &str = r#"# Maintainer: Giancarlo Razzolini <grazzolini@archlinux.org>
+# Contributor: Allan McRae <allan@archlinux.org>
+
+pkgname=which
+pkgver=2.21
+pkgrel=6
+pkgdesc='A utility to show the full path of commands'
+arch=('x86_64')
+url='https://savannah.gnu.org/projects/which/'
+license=('GPL3')
+depends=('glibc' 'bash')
+validpgpkeys=('E3C4A8E68A3AD4FD0C02DC1C8BB8E6A4C4E9EC1B') # Carlo Wood
+source=("https://ftp.gnu.org/gnu/${pkgname}/${pkgname}-${pkgver}.tar.gz"{,.sig})
+sha256sums=('f4a245b94124b377d8b49646bf421f9155d36aa7614b6ebf83705d3ffc76eaad'
+            'SKIP')
+
+build() {
+  cd $pkgname-$pkgver
+  ./configure --prefix=/usr
+  make
+}
+
+package() {
+  cd $pkgname-$pkgver
+  make DESTDIR="$pkgdir" install
+}
+"#;
+
+    // core/zlib, with the split package functions
+    const ZLIB: &str = r#"pkgbase=zlib
+pkgname=(zlib minizip)
+epoch=1
+pkgver=1.3.1
+pkgrel=2
+pkgdesc='Compression library implementing the deflate compression method found in gzip and PKZIP'
+arch=('x86_64')
+license=('Zlib')
+url="https://www.zlib.net/"
+depends=('glibc')
+options=('staticlibs')  # needed by binutils testsuite
+source=("https://github.com/madler/zlib/releases/download/v${pkgver}/zlib-${pkgver}.tar.xz"{,.asc})
+sha256sums=('38ef96b8dfe510d42707d9c781877914792541133e1870841463bfa73f883e32'
+            'SKIP')
+
+prepare() {
+  cd ${pkgbase}-${pkgver}/contrib/minizip
+  cp Makefile Makefile.orig
+  cp ../README.contrib readme.txt
+  autoreconf --install
+}
+
+build() {
+  cd ${pkgbase}-${pkgver}
+  ./configure --prefix=/usr
+  make
+}
+
+package_zlib() {
+  cd ${pkgbase}-${pkgver}
+  make install DESTDIR="${pkgdir}"
+}
+
+package_minizip() {
+  pkgdesc='Mini zip and unzip based on zlib'
+  cd ${pkgbase}-${pkgver}/contrib/minizip
+  make install DESTDIR="${pkgdir}"
+}
+"#;
+
+    fn scalar(value: &str) -> Value {
+        Value::Scalar(value.to_owned())
+    }
+
+    fn array(words: &[&str]) -> Value {
+        Value::Array(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_values_and_functions() {
+        let recipe = parse(WHICH);
+        assert_eq!(recipe.values["pkgname"], (4, scalar("which")));
+        assert_eq!(recipe.values["pkgdesc"], (7, scalar("A utility to show the full path of commands")));
+        assert_eq!(recipe.values["depends"], (11, array(&["glibc", "bash"])));
+        assert_eq!(recipe.values["validpgpkeys"], (12, array(&["E3C4A8E68A3AD4FD0C02DC1C8BB8E6A4C4E9EC1B"])));
+        assert_eq!(
+            recipe.values["source"],
+            (13, array(&["https://ftp.gnu.org/gnu/which/which-2.21.tar.gz", "https://ftp.gnu.org/gnu/which/which-2.21.tar.gz.sig"]))
+        );
+        assert_eq!(recipe.values["sha256sums"], (14, array(&["f4a245b94124b377d8b49646bf421f9155d36aa7614b6ebf83705d3ffc76eaad", "SKIP"])));
+        assert!(recipe.problems.is_empty(), "{:?}", recipe.problems);
+
+        let functions = recipe.functions.iter().map(|function| (function.name.as_str(), function.line, function.body.len())).collect::<Vec<_>>();
+        assert_eq!(functions, [("build", 17, 3), ("package", 23, 2)]);
+        assert_eq!(recipe.functions[1].body[1], (25, "  make DESTDIR=\"$pkgdir\" install".to_owned()));
+    }
+
+    #[test]
+    fn parses_split_packages() {
+        let recipe = parse(ZLIB);
+        assert_eq!(recipe.values["pkgname"].1, array(&["zlib", "minizip"]));
+        assert_eq!(recipe.values["options"].1, array(&["staticlibs"]));
+        assert_eq!(
+            recipe.values["source"].1,
+            array(&[
+                "https://github.com/madler/zlib/releases/download/v1.3.1/zlib-1.3.1.tar.xz",
+                "https://github.com/madler/zlib/releases/download/v1.3.1/zlib-1.3.1.tar.xz.asc"
+            ])
+        );
+        let names = recipe.functions.iter().map(|function| function.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, ["prepare", "build", "package_zlib", "package_minizip"]);
+    }
+
+    #[test]
+    fn reports_what_is_not_translated() {
+        let recipe = parse("pkgname=foo\npkgver=$(date +%Y%m%d)\nsource=(\"$_url/foo.tar.gz\")\n[[ $CARCH = x86_64 ]] && depends+=(lib32-glibc)\n");
+        assert_eq!(
+            recipe.problems,
+            [
+                "line 2: pkgver runs a command, it is not translated",
+                "line 3: $_url in source is not translated",
+                "line 4: `[[ $CARCH = x86_64 ]] && depends+=(lib32-glibc)` is not translated"
+            ]
+        );
+        assert_eq!(recipe.values["source"].1, array(&["$_url/foo.tar.gz"]));
+    }
+
+    #[test]
+    fn appends_to_arrays() {
+        let recipe = parse("makedepends=('cmake' 'ninja')\nmakedepends+=('python')\n");
+        assert_eq!(recipe.values["makedepends"], (1, array(&["cmake", "ninja", "python"])));
+    }
+
+    #[test]
+    fn splits_values() {
+        assert_eq!(split_value("'Compression library' # a comment"), Ok(scalar("Compression library")));
+        assert_eq!(split_value("\"https://www.zlib.net/\""), Ok(scalar("https://www.zlib.net/")));
+        assert_eq!(split_value(""), Ok(scalar("")));
+        assert_eq!(split_value("('glibc' 'bash')"), Ok(array(&["glibc", "bash"])));
+        assert_eq!(
+            split_value("('38ef96b8dfe510d42707d9c781877914792541133e1870841463bfa73f883e32'\n            'SKIP')"),
+            Ok(array(&["38ef96b8dfe510d42707d9c781877914792541133e1870841463bfa73f883e32", "SKIP"]))
+        );
+        assert_eq!(split_value("(\n  'glibc' # libc\n  'zlib'\n)"), Ok(array(&["glibc", "zlib"])));
+        assert_eq!(split_value("()"), Ok(array(&[])));
+        assert_eq!(split_value("('glibc'"), Err("the array is not closed".to_owned()));
+        assert_eq!(split_value("GPL LGPL"), Err("the value is not a single word".to_owned()));
+        assert!(split_value("'unterminated").is_err());
+    }
+
+    #[test]
+    fn expands_brace_lists() {
+        assert_eq!(expand_braces("zlib-1.3.1.tar.xz{,.asc}"), ["zlib-1.3.1.tar.xz", "zlib-1.3.1.tar.xz.asc"]);
+        assert_eq!(
+            expand_braces("https://ftp.gnu.org/gnu/${pkgname}/${pkgname}-${pkgver}.tar.gz{,.sig}"),
+            [
+                "https://ftp.gnu.org/gnu/${pkgname}/${pkgname}-${pkgver}.tar.gz",
+                "https://ftp.gnu.org/gnu/${pkgname}/${pkgname}-${pkgver}.tar.gz.sig"
+            ]
+        );
+        assert_eq!(expand_braces("{gcc,gcc-libs}-{13,14}"), ["gcc-13", "gcc-14", "gcc-libs-13", "gcc-libs-14"]);
+        assert_eq!(expand_braces("${pkgname}.tar.gz"), ["${pkgname}.tar.gz"]);
+        assert_eq!(expand_braces("file{single}.patch"), ["file{single}.patch"]);
+        assert_eq!(expand_braces("file{a,b"), ["file{a,b"]);
+    }
+
+    #[test]
+    fn replaces_variables() {
+        let known = |variable: &str, rest: &str, _| match (variable, rest) {
+            ("pkgname", "") => Some("zlib".to_owned()),
+            ("pkgver", "") => Some("1.3.1".to_owned()),
+            _ => None,
+        };
+        assert_eq!(replace_variables("cd ${pkgname}-${pkgver}/contrib/minizip", known), "cd zlib-1.3.1/contrib/minizip");
+        assert_eq!(replace_variables("cd $pkgname-$pkgver", known), "cd zlib-1.3.1");
+        assert_eq!(replace_variables("${pkgver//./_} $_commit $1 $@ cost $5", known), "${pkgver//./_} $_commit $1 $@ cost $5");
+        assert_eq!(replace_variables("make DESTDIR=\"$pkgdir\" install", known), "make DESTDIR=\"$pkgdir\" install");
+        assert_eq!(replace_variables("trailing $", known), "trailing $");
+
+        let mut seen = Vec::new();
+        replace_variables("${pkgname%-*} $pkgdir ${srcdir}", |variable, rest, braced| {
+            seen.push((variable.to_owned(), rest.to_owned(), braced));
+            None
+        });
+        assert_eq!(
+            seen,
+            [
+                ("pkgname".to_owned(), "%-*".to_owned(), true),
+                ("pkgdir".to_owned(), String::new(), false),
+                ("srcdir".to_owned(), String::new(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_function_headers() {
+        assert_eq!(parse_function_header("build() {"), Some(("build".to_owned(), true)));
+        assert_eq!(parse_function_header("package_zlib()"), Some(("package_zlib".to_owned(), false)));
+        assert_eq!(parse_function_header("prepare () {"), Some(("prepare".to_owned(), true)));
+        assert_eq!(parse_function_header("function package {"), Some(("package".to_owned(), true)));
+        assert_eq!(parse_function_header("function check()"), Some(("check".to_owned(), false)));
+        assert_eq!(parse_function_header("pkgver=1.3.1"), None);
+        assert_eq!(parse_function_header("build() { make; }"), None);
+        assert_eq!(parse_function_header("() {"), None);
+    }
+
+    #[test]
+    fn translates_functions() {
+        let mut recipe = parse(WHICH);
+        let pkgb = to_pkgbuild(&mut recipe);
+        assert_eq!(
+            pkgb.build_script,
+            [
+                "\t# build()",
+                "\t(",
+                "\t\tset -e",
+                "\t\tcd $PKG_NAME-$PKG_VERSION",
+                "\t\t./configure --prefix=/usr",
+                "\t\tmake",
+                "\t) || exit 1",
+                "\t# package()",
+                "\t(",
+                "\t\tset -e",
+                "\t\tcd $PKG_NAME-$PKG_VERSION",
+                "\t\tmake DESTDIR=\"$PKG_INSTALL_DIR\" install",
+                "\t) || exit 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_here_documents_as_written() {
+        // extra/jdk-openjdk style, the body keeps its indentation and is only expanded if the delimiter is unquoted
+        let mut recipe = parse(
+            "pkgname=foo\npkgver=1.0\npackage() {\n  cat > \"$pkgdir\"/usr/bin/foo <<'EOF'\n#!/bin/sh\n  exec /usr/lib/foo \"$@\"\nEOF\n  cat > \"$pkgdir\"/etc/foo.conf <<EOF\n\tversion = $pkgver\n  dir = $pkgdir\nEOF\n  chmod 755 \"$pkgdir\"/usr/bin/foo\n}\n",
+        );
+        let pkgb = to_pkgbuild(&mut recipe);
+        assert_eq!(
+            pkgb.build_script,
+            [
+                "\t# package()",
+                "\t(",
+                "\t\tset -e",
+                "\t\tcat > \"$PKG_INSTALL_DIR\"/usr/bin/foo <<'EOF'",
+                "#!/bin/sh",
+                "  exec /usr/lib/foo \"$@\"",
+                "EOF",
+                "\t\tcat > \"$PKG_INSTALL_DIR\"/etc/foo.conf <<EOF",
+                "\tversion = $PKG_VERSION",
+                "  dir = $PKG_INSTALL_DIR",
+                "EOF",
+                "\t\tchmod 755 \"$PKG_INSTALL_DIR\"/usr/bin/foo",
+                "\t) || exit 1",
+            ]
+        );
+        assert!(recipe.problems.is_empty(), "{:?}", recipe.problems);
+    }
+
+    #[test]
+    fn closing_braces_in_here_documents_do_not_end_the_function() {
+        let mut recipe = parse("pkgname=foo\npkgver=1.0\npackage() {\n  cat > \"$pkgdir\"/etc/foo.json <<EOF\n{\n  \"version\": \"$pkgver\"\n}\nEOF\n  chmod 644 \"$pkgdir\"/etc/foo.json\n}\n");
+        let pkgb = to_pkgbuild(&mut recipe);
+        assert_eq!(
+            pkgb.build_script,
+            [
+                "\t# package()",
+                "\t(",
+                "\t\tset -e",
+                "\t\tcat > \"$PKG_INSTALL_DIR\"/etc/foo.json <<EOF",
+                "{",
+                "  \"version\": \"$PKG_VERSION\"",
+                "}",
+                "EOF",
+                "\t\tchmod 644 \"$PKG_INSTALL_DIR\"/etc/foo.json",
+                "\t) || exit 1",
+            ]
+        );
+        assert!(recipe.problems.is_empty(), "{:?}", recipe.problems);
+    }
+
+    #[test]
+    fn keeps_single_quoted_strings_as_written() {
+        let mut recipe = parse(
+            "pkgname=foo\npkgver=1.0\npackage() {\n  sed -i 's/$pkgver/\"$pkgver\"/' \"$pkgdir\"/foo.pc # don't touch $pkgdir\n  echo \"it's $pkgver\" > \"$pkgdir\"/version\n  awk '{\n    print $pkgdir\n  }' \"$pkgdir\"/list\n}\n",
+        );
+        let pkgb = to_pkgbuild(&mut recipe);
+        assert_eq!(
+            pkgb.build_script,
+            [
+                "\t# package()",
+                "\t(",
+                "\t\tset -e",
+                "\t\tsed -i 's/$pkgver/\"$pkgver\"/' \"$PKG_INSTALL_DIR\"/foo.pc # don't touch $pkgdir",
+                "\t\techo \"it's $PKG_VERSION\" > \"$PKG_INSTALL_DIR\"/version",
+                "\t\tawk '{",
+                "\t\t  print $pkgdir",
+                "\t\t}' \"$PKG_INSTALL_DIR\"/list",
+                "\t) || exit 1",
+            ]
+        );
+        assert!(recipe.problems.is_empty(), "{:?}", recipe.problems);
+    }
+
+    #[test]
+    fn finds_here_documents() {
+        assert_eq!(parse_here_document("cat > \"$pkgdir\"/etc/foo.conf <<EOF"), Some(("EOF".to_owned(), false, true)));
+        assert_eq!(parse_here_document("cat <<'EOF' > foo.sh"), Some(("EOF".to_owned(), false, false)));
+        assert_eq!(parse_here_document("cat <<-\"END\" | tee foo"), Some(("END".to_owned(), true, false)));
+        assert_eq!(parse_here_document("patch -p1 << \\EOF"), Some(("EOF".to_owned(), false, false)));
+        assert_eq!(parse_here_document("read -r major minor <<< \"$pkgver\""), None);
+        assert_eq!(parse_here_document("echo $((1 << 4))"), None);
+        assert_eq!(parse_here_document("make install"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Read;
+
+use console::Style;
+use log::{error, warn};
+
+use crate::{
+    args::{
+        cli::{try_parse, Command, Parsed},
+        legacy::translate,
+    },
+    structs::{client::Client, config::config_main::Config},
+};
+
+use super::{
+    funcs::{is_connection_lost, truncate_to},
+    shell::{run_command, Handler},
+};
+
+// a command read from a batch file
+struct Entry {
+    line: usize,
+    // the command as it runs, legacy flags already translated
+    text: String,
+    parsed: Parsed,
+}
+
+enum Outcome {
+    Done,
+    Failed(String),
+    Skipped,
+}
+
+// runs the commands of the file in order and prints a report, stops at the first failure unless continue_on_error
+pub fn run(client: &mut Client, config: &Config, handle: Handler, file: &str, continue_on_error: bool) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    let entries = parse(file)?;
+
+    let mut outcomes = Vec::new();
+    let mut stopped = false;
+    for entry in &entries {
+        if stopped {
+            outcomes.push(Outcome::Skipped);
+            continue;
+        }
+        println!("{}", bold.apply_to(format!("[{}] {}", entry.line, entry.text)));
+        match run_command(client, config, handle, &entry.parsed.cli) {
+            Ok(()) => outcomes.push(Outcome::Done),
+            Err(err) => {
+                error!("Failed on line {} ({}), reason: {err}", entry.line, entry.parsed.name);
+                // nothing after it could reach the master either
+                stopped = !continue_on_error || is_connection_lost(&err);
+                outcomes.push(Outcome::Failed(err.to_string()));
+            }
+        }
+    }
+
+    print_report(&entries, &outcomes);
+    let failed = outcomes.iter().filter(|outcome| matches!(outcome, Outcome::Failed(_))).count();
+    if failed > 0 {
+        return Err(std::io::Error::other(format!("{failed} of {} commands failed", entries.len())));
+    }
+    Ok(())
+}
+
+fn print_report(entries: &[Entry], outcomes: &[Outcome]) {
+    let bold = Style::new().bold();
+    let italic = Style::new().italic();
+    let red = Style::new().red();
+    let green = Style::new().green();
+    let yellow = Style::new().yellow();
+
+    println!();
+    println!("{}", italic.apply_to(format!("{:<6} {:<50} {}", "LINE", "COMMAND", "RESULT")));
+    for (entry, outcome) in entries.iter().zip(outcomes) {
+        let result = match outcome {
+            Outcome::Done => green.apply_to("ok".to_owned()),
+            Outcome::Failed(reason) => red.apply_to(format!("failed: {reason}")),
+            Outcome::Skipped => yellow.apply_to("skipped".to_owned()),
+        };
+        println!("{:<6} {:<50} {}", entry.line, truncate_to(entry.text.clone(), 50), result);
+    }
+
+    let count = |wanted: fn(&Outcome) -> bool| outcomes.iter().filter(|outcome| wanted(outcome)).count();
+    println!(
+        "{}",
+        bold.apply_to(format!(
+            "{} succeeded, {} failed, {} skipped",
+            count(|outcome| matches!(outcome, Outcome::Done)),
+            count(|outcome| matches!(outcome, Outcome::Failed(_))),
+            count(|outcome| matches!(outcome, Outcome::Skipped))
+        ))
+    );
+}
+
+// every line is parsed before anything runs, so a typo does not leave a batch half done.
+// empty lines and lines starting with # are skipped
+fn parse(file: &str) -> Result<Vec<Entry>, std::io::Error> {
+    let content = if file == "-" {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        std::fs::read_to_string(file)?
+    };
+
+    let mut entries = Vec::new();
+    let mut problems = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(parsed) => entries.extend(parsed.into_iter().map(|(text, parsed)| Entry { line: index + 1, text, parsed })),
+            Err(err) => problems.push(format!("line {}: {err}", index + 1)),
+        }
+    }
+
+    if !problems.is_empty() {
+        problems.iter().for_each(|problem| error!("{problem}"));
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} invalid lines in {file}, nothing was run", problems.len())));
+    }
+    Ok(entries)
+}
+
+// one line can hold several legacy commands, eg. -c zlib -rb zlib
+fn parse_line(line: &str) -> Result<Vec<(String, Parsed)>, String> {
+    let words = shell_words::split(line).map_err(|err| err.to_string())?;
+    let argvs = translate([vec!["rranch".to_owned()], words].concat())?;
+
+    let mut parsed = Vec::new();
+    for argv in argvs {
+        let text = shell_words::join(&argv[1..]);
+        let command = try_parse(argv).map_err(|err| {
+            let rendered = err.render().to_string();
+            rendered.lines().next().unwrap_or_default().trim_start_matches("error: ").to_owned()
+        })?;
+        if !command.overrides.is_empty() || command.cli.profile.is_some() {
+            warn!("Config overrides and --profile only apply when starting rranch, ignoring them in {text}");
+        }
+        if matches!(command.cli.command, Command::Shell | Command::Batch { .. }) {
+            return Err(format!("{} can not run in a batch", command.name));
+        }
+        parsed.push((text, command));
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,261 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use console::Style;
+use indicatif::HumanBytes;
+use log::{info, trace};
+
+use crate::structs::config::config_cache::Cache;
+
+use super::download::{sha256_of, sha256sum};
+
+struct CacheEntry {
+    path: PathBuf,
+    pkgname: String,
+    version: String,
+    size: u64,
+    last_used: SystemTime,
+}
+
+// replaces characters that would escape the cache directory
+fn sanitize(part: &str) -> String {
+    part.replace(['/', '\\'], "_").replace("..", "_")
+}
+
+// a rebuild does not have to change the version, so entries are also keyed by what the server reports about the
+// content of the package (see fetch_validator)
+fn entry_path(cache: &Cache, pkgname: &str, version: &str, real_version: &str, validator: &str) -> PathBuf {
+    Path::new(&cache.get_dir())
+        .join("packages")
+        .join(sanitize(pkgname))
+        .join(format!("{}-{}@{}.tar.xz", sanitize(version), sanitize(real_version), &sha256_of(validator.as_bytes())[..16]))
+}
+
+// copies a cached package to path, returns false on a cache miss
+pub fn restore(cache: &Cache, pkgname: &str, version: &str, real_version: &str, validator: &str, path: &str) -> Result<bool, std::io::Error> {
+    let entry = entry_path(cache, pkgname, version, real_version, validator);
+    if !entry.is_file() {
+        trace!("Cache miss for {pkgname} {version}-{real_version}");
+        return Ok(false);
+    }
+
+    // another fetch may have pruned the entry in the meantime
+    match std::fs::copy(&entry, path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            trace!("Cache miss for {pkgname} {version}-{real_version}, pruned meanwhile");
+            return Ok(false);
+        }
+        result => result?,
+    };
+    // the modification time doubles as last use for pruning
+    match std::fs::File::options().append(true).open(&entry).and_then(|file| file.set_modified(SystemTime::now())) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    info!("Using cached {pkgname} {version}-{real_version}");
+    Ok(true)
+}
+
+// copies a fetched package into the cache and prunes it down to the configured size
+pub fn store(cache: &Cache, pkgname: &str, version: &str, real_version: &str, validator: &str, path: &str) -> Result<(), std::io::Error> {
+    let entry = entry_path(cache, pkgname, version, real_version, validator);
+    std::fs::create_dir_all(entry.parent().unwrap_or(Path::new("")))?;
+
+    let tmp = entry.with_extension("xz.tmp");
+    std::fs::copy(path, &tmp)?;
+    std::fs::rename(&tmp, &entry)?;
+    trace!("Cached {pkgname} {version}-{real_version} at {}", entry.display());
+
+    prune(cache, false)
+}
+
+// drops a cached package that turned out to be damaged
+pub fn evict(cache: &Cache, pkgname: &str, version: &str, real_version: &str, validator: &str) -> Result<(), std::io::Error> {
+    let entry = entry_path(cache, pkgname, version, real_version, validator);
+    if remove_entry(&entry)? {
+        info!("Removed {pkgname} {version}-{real_version} from the cache");
+    }
+    Ok(())
+}
+
+// removes a cache entry, returns false if it was already gone. parallel fetches prune the same cache, so another
+// one may have removed it first
+fn remove_entry(path: &Path) -> Result<bool, std::io::Error> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn entries(cache: &Cache) -> Result<Vec<CacheEntry>, std::io::Error> {
+    let root = Path::new(&cache.get_dir()).join("packages");
+    let mut entries = Vec::new();
+    if !root.is_dir() {
+        return Ok(entries);
+    }
+
+    for pkg in std::fs::read_dir(root)?.flatten() {
+        if !pkg.file_type()?.is_dir() {
+            continue;
+        }
+        for file in std::fs::read_dir(pkg.path())?.flatten() {
+            // pruned by a parallel fetch since it was listed
+            let metadata = match file.metadata() {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                metadata => metadata?,
+            };
+            let filename = file.file_name().to_str().unwrap_or_default().to_owned();
+            if !metadata.is_file() || !filename.ends_with(".tar.xz") {
+                continue;
+            }
+            entries.push(CacheEntry {
+                path: file.path(),
+                pkgname: pkg.file_name().to_str().unwrap_or_default().to_owned(),
+                version: filename.trim_end_matches(".tar.xz").split('@').next().unwrap_or_default().to_owned(),
+                size: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.pkgname.cmp(&b.pkgname).then(a.version.cmp(&b.version)));
+    Ok(entries)
+}
+
+pub fn show_cache(cache: &Cache) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    let italic = Style::new().italic();
+
+    let entries = entries(cache)?;
+    println!("{}", bold.apply_to(format!("Cached packages in {}", cache.get_dir())));
+    println!("{}", italic.apply_to(format!("{:<30} {:<25} {:>12} {}", "NAME", "VERSION", "SIZE", "UNUSED FOR")));
+    for entry in &entries {
+        let unused = SystemTime::now().duration_since(entry.last_used).unwrap_or_default();
+        println!(
+            "{:<30} {:<25} {:>12} {}d {}h",
+            entry.pkgname,
+            entry.version,
+            HumanBytes(entry.size).to_string(),
+            unused.as_secs() / 86400,
+            unused.as_secs() / 3600 % 24
+        );
+    }
+    println!(
+        "{}",
+        bold.apply_to(format!(
+            "Total {} of {}",
+            HumanBytes(entries.iter().map(|entry| entry.size).sum()),
+            HumanBytes(cache.get_max_size_mb() * 1024 * 1024)
+        ))
+    );
+    Ok(())
+}
+
+// removes the least recently used packages until the cache fits max_size_mb
+pub fn prune(cache: &Cache, verbose: bool) -> Result<(), std::io::Error> {
+    let mut entries = entries(cache)?;
+    entries.sort_by_key(|entry| entry.last_used);
+
+    let max = cache.get_max_size_mb() * 1024 * 1024;
+    let mut total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    let mut removed = 0;
+    for entry in entries {
+        if total <= max {
+            break;
+        }
+        total -= entry.size;
+        if !remove_entry(&entry.path)? {
+            continue;
+        }
+        removed += 1;
+        if verbose {
+            println!("Removed {} {}", entry.pkgname, entry.version);
+        } else {
+            trace!("Pruned {} {} from cache", entry.pkgname, entry.version);
+        }
+    }
+    if verbose {
+        info!("Pruned {removed} packages, cache now holds {}", HumanBytes(total));
+    }
+    Ok(())
+}
+
+pub fn clear_cache(cache: &Cache) -> Result<(), std::io::Error> {
+    let root = Path::new(&cache.get_dir()).join("packages");
+    if root.is_dir() {
+        std::fs::remove_dir_all(&root)?;
+    }
+    info!("Cleared {}", root.display());
+    Ok(())
+}
+
+// places a package in a mirror tree other clients can use as fetch_url with mirror_layout enabled
+pub fn mirror_package(mirror: &str, pkgname: &str, path: &str) -> Result<(), std::io::Error> {
+    let packages = Path::new(mirror).join("packages");
+    std::fs::create_dir_all(&packages)?;
+
+    let target = packages.join(format!("{}.tar.xz", sanitize(pkgname)));
+    let tmp = target.with_extension("xz.tmp");
+    std::fs::copy(path, &tmp)?;
+    std::fs::rename(&tmp, &target)?;
+    std::fs::write(packages.join(format!("{}.tar.xz.sha256", sanitize(pkgname))), sha256sum(path)?)?;
+
+    write_mirror_index(mirror)
+}
+
+fn write_mirror_index(mirror: &str) -> Result<(), std::io::Error> {
+    let mut names = std::fs::read_dir(Path::new(mirror).join("packages"))?
+        .flatten()
+        .map(|file| file.file_name().to_str().unwrap_or_default().to_owned())
+        .filter(|name| name.ends_with(".tar.xz"))
+        .collect::<Vec<String>>();
+    names.sort();
+
+    let links = names
+        .iter()
+        .map(|name| format!("<li><a href=\"packages/{name}\">{name}</a> (<a href=\"packages/{name}.sha256\">sha256</a>)</li>"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    std::fs::write(
+        Path::new(mirror).join("index.html"),
+        format!("<!DOCTYPE html>\n<html>\n<head><title>rranch mirror</title></head>\n<body>\n<h1>Packages</h1>\n<ul>\n{links}\n</ul>\n</body>\n</html>\n"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::structs::config::config_cache::Cache;
+
+    use super::{entries, prune};
+
+    #[test]
+    fn concurrent_prunes_do_not_fail_on_entries_removed_by_each_other() {
+        let dir = std::env::temp_dir().join(format!("rranch-cache-prune-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = toml::from_str::<Cache>(&format!("dir = {:?}\nmax_size_mb = 0", dir.display().to_string())).unwrap();
+        for index in 0..200 {
+            let pkg = Path::new(&cache.get_dir()).join("packages").join(format!("pkg{index}"));
+            std::fs::create_dir_all(&pkg).unwrap();
+            std::fs::write(pkg.join("1.0-1@0123456789abcdef.tar.xz"), b"package").unwrap();
+        }
+
+        // like the workers of a fetch with dependencies, each storing a package and pruning afterwards
+        let results = std::thread::scope(|scope| {
+            (0..4)
+                .map(|_| scope.spawn(|| prune(&cache, false)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for result in results {
+            result.unwrap();
+        }
+        assert!(entries(&cache).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
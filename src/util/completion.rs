@@ -0,0 +1,221 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use clap_complete::Shell;
+
+use crate::{
+    args::cli::{command, CompletionKind},
+    structs::{client::Client, config::config_main::Config},
+};
+
+// candidates are fetched again once the cached ones are older than this
+const CACHE_TTL: Duration = Duration::from_secs(60);
+// an unreachable master must not hang the shell, the stale cache is used instead
+const FETCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+// subcommands whose first positional value is completed from the master
+const DYNAMIC: &[(&str, &[&str], CompletionKind)] = &[
+    ("pkgb", &["checkout", "edit", "bump"], CompletionKind::Pkg),
+    (
+        "pkg",
+        &["build", "fetch", "inspect", "info", "remove", "dependers", "dependencies", "rebuild-dependers", "rebuild-dependencies"],
+        CompletionKind::Pkg,
+    ),
+    ("job", &["log", "cancel"], CompletionKind::Job),
+    ("extrasource", &["remove", "fetch"], CompletionKind::Extrasource),
+    ("extrasource", &["fetch-for"], CompletionKind::Pkg),
+    ("es", &["remove", "fetch"], CompletionKind::Extrasource),
+    ("es", &["fetch-for"], CompletionKind::Pkg),
+];
+
+fn get_kind_name(kind: CompletionKind) -> &'static str {
+    match kind {
+        CompletionKind::Pkg => "pkg",
+        CompletionKind::Job => "job",
+        CompletionKind::Extrasource => "extrasource",
+    }
+}
+
+// the kind of value completed after a group and subcommand, eg. pkg after pkgb checkout
+pub fn get_kind(group: &str, subcommand: &str) -> Option<CompletionKind> {
+    DYNAMIC.iter().find(|(name, commands, _)| *name == group && commands.contains(&subcommand)).map(|(_, _, kind)| *kind)
+}
+
+// the generated script followed by the glue calling `rranch __complete` for the values in DYNAMIC
+pub fn print_script(shell: Shell) -> Result<(), std::io::Error> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command(), "rranch", &mut script);
+    let script = String::from_utf8_lossy(&script).to_string();
+
+    let script = match shell {
+        Shell::Bash => format!("{script}\n{}", bash_glue()),
+        // the generated script calls or registers _rranch depending on how it is loaded
+        Shell::Zsh => format!("{}\n{}", zsh_glue(), script.replace("    _rranch \"$@\"\nelse\n    compdef _rranch rranch", "    _rranch_dynamic \"$@\"\nelse\n    compdef _rranch_dynamic rranch")),
+        Shell::Fish => format!("{script}\n{}", fish_glue()),
+        _ => script,
+    };
+    print!("{script}");
+    Ok(())
+}
+
+// the glue looks for the group and subcommand among the words before the cursor, skipping flags and their values
+fn bash_glue() -> String {
+    let cases = get_cases();
+    let value_flags = get_value_flags();
+    format!(
+        r#"_rranch_dynamic() {{
+    local kind="" words=() i=1
+    while (( i < COMP_CWORD )); do
+        case "${{COMP_WORDS[i]}}" in
+            {value_flags})
+                # bash splits --flag=value into three words
+                [[ "${{COMP_WORDS[i+1]}}" == "=" ]] && (( i++ ))
+                (( i++ )) ;;
+            -*) ;;
+            *) words+=("${{COMP_WORDS[i]}}") ;;
+        esac
+        (( i++ ))
+    done
+    if (( i == COMP_CWORD && ${{#words[@]}} == 2 )); then
+        case "${{words[0]}} ${{words[1]}}" in
+{cases}
+        esac
+    fi
+    if [[ -n "${{kind}}" ]]; then
+        COMPREPLY=( $(compgen -W "$(rranch __complete ${{kind}} 2>/dev/null)" -- "${{COMP_WORDS[COMP_CWORD]}}") )
+    else
+        _rranch "$@"
+    fi
+}}
+
+complete -F _rranch_dynamic -o bashdefault -o default rranch
+"#
+    )
+}
+
+fn zsh_glue() -> String {
+    let cases = get_cases();
+    let value_flags = get_value_flags();
+    format!(
+        r#"_rranch_dynamic() {{
+    local kind="" i=2
+    local -a positional
+    while (( i < CURRENT )); do
+        case "$words[i]" in
+            {value_flags}) (( i++ )) ;;
+            -*) ;;
+            *) positional+=("$words[i]") ;;
+        esac
+        (( i++ ))
+    done
+    if (( i == CURRENT && $#positional == 2 )); then
+        case "$positional[1] $positional[2]" in
+{cases}
+        esac
+    fi
+    if [[ -n "$kind" ]]; then
+        compadd -- ${{(f)"$(rranch __complete $kind 2>/dev/null)"}}
+    else
+        _rranch "$@"
+    fi
+}}
+"#
+    )
+}
+
+// case patterns shared by bash and zsh, eg. "pkgb checkout"|"pkgb edit") kind=pkg ;;
+fn get_cases() -> String {
+    DYNAMIC
+        .iter()
+        .map(|(group, commands, kind)| {
+            format!(
+                "            {}) kind={} ;;",
+                commands.iter().map(|name| format!("\"{group} {name}\"")).collect::<Vec<String>>().join("|"),
+                get_kind_name(*kind)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// case pattern of every flag taking a value, eg. --profile|--output
+fn get_value_flags() -> String {
+    fn collect(command: &clap::Command, flags: &mut Vec<String>) {
+        for arg in command.get_arguments().filter(|arg| !arg.is_positional() && arg.get_action().takes_values()) {
+            flags.extend(arg.get_long().map(|long| format!("--{long}")));
+            flags.extend(arg.get_short().map(|short| format!("-{short}")));
+        }
+        command.get_subcommands().for_each(|subcommand| collect(subcommand, flags));
+    }
+
+    let mut flags = Vec::new();
+    collect(&command(), &mut flags);
+    flags.sort();
+    flags.dedup();
+    flags.join("|")
+}
+
+fn fish_glue() -> String {
+    DYNAMIC
+        .iter()
+        .map(|(group, commands, kind)| {
+            format!(
+                "complete -c rranch -n \"__fish_seen_subcommand_from {group}; and __fish_seen_subcommand_from {}\" -f -a \"(rranch __complete {} 2>/dev/null)\"\n",
+                commands.join(" "),
+                get_kind_name(*kind)
+            )
+        })
+        .collect()
+}
+
+// prints one candidate per line
+pub fn complete(config: &Config, kind: CompletionKind) -> Result<(), std::io::Error> {
+    let candidates = get_candidates(config, kind)?;
+    if !candidates.is_empty() {
+        println!("{}", candidates.join("\n"));
+    }
+    Ok(())
+}
+
+// failures return nothing, the cache is used as long as the master can not be reached
+pub fn get_candidates(config: &Config, kind: CompletionKind) -> Result<Vec<String>, std::io::Error> {
+    let path = get_cache_path(config, kind);
+    let fresh = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| SystemTime::now().duration_since(modified).unwrap_or_default() < CACHE_TTL)
+        .unwrap_or(false);
+
+    if !fresh {
+        if let Ok(candidates) = fetch(config, kind) {
+            std::fs::create_dir_all(path.parent().unwrap_or(&path))?;
+            std::fs::write(&path, candidates.join("\n"))?;
+        }
+    }
+    Ok(std::fs::read_to_string(&path).unwrap_or_default().lines().map(str::to_owned).collect())
+}
+
+// one cache per profile, since profiles usually point at different masters
+fn get_cache_path(config: &Config, kind: CompletionKind) -> PathBuf {
+    PathBuf::from(config.get_cache().get_dir())
+        .join("completion")
+        .join(format!("{}-{}", config.get_profile_name().unwrap_or("default".to_owned()), get_kind_name(kind)))
+}
+
+fn fetch(config: &Config, kind: CompletionKind) -> Result<Vec<String>, std::io::Error> {
+    let master = config.get_master();
+    let mut client = Client::with_timeout(&master.get_addr(), master.get_port(), FETCH_TIMEOUT)?;
+    client.auth(&config.get_client().get_name(), &config.get_client().get_type(), &config.get_authkey()?, config.get_client().get_protver())?;
+
+    let candidates = match kind {
+        CompletionKind::Pkg => client.get_all()?,
+        CompletionKind::Job => {
+            let jobs = client.get_jobs()?;
+            [jobs.queuedjobs, jobs.runningjobs, jobs.completedjobs].into_iter().flatten().map(|job| job.job_id).collect()
+        }
+        CompletionKind::Extrasource => client.get_extra_sources()?.into_iter().map(|es| es.id).collect(),
+    };
+    client.shutdown()?;
+    Ok(candidates)
+}
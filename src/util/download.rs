@@ -0,0 +1,568 @@
+use std::{
+    cell::Cell,
+    io::{Read, Write},
+};
+
+use curl::easy::Easy;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{error, info, trace, warn};
+use sha2::{Digest, Sha256};
+
+pub enum Checksum<'a> {
+    Skip,
+    // verifies against the checksum at the url, warns if the master does not publish one
+    IfPublished(&'a str),
+    // verifies against the checksum at the url, fails if the master does not publish one
+    Required(&'a str),
+}
+
+pub fn progress_style() -> Result<ProgressStyle, std::io::Error> {
+    match ProgressStyle::with_template("{percent:>3}% [{bar:.green/white}] {bytes:>7}/{total_bytes:>7} ({bytes_per_sec})") {
+        Ok(pstyle) => Ok(pstyle.progress_chars("#=--")),
+        Err(err) => Err(std::io::Error::other(format!("Failed setting progress style: {err}"))),
+    }
+}
+
+// progress style showing the prefix of the bar, for multiple bars at once
+pub fn multi_progress_style() -> Result<ProgressStyle, std::io::Error> {
+    match ProgressStyle::with_template("{prefix:<24} {percent:>3}% [{bar:.green/white}] {bytes:>7}/{total_bytes:>7} ({bytes_per_sec})") {
+        Ok(pstyle) => Ok(pstyle.progress_chars("#=--")),
+        Err(err) => Err(std::io::Error::other(format!("Failed setting progress style: {err}"))),
+    }
+}
+
+// progress style counting items instead of bytes
+pub fn count_progress_style() -> Result<ProgressStyle, std::io::Error> {
+    match ProgressStyle::with_template("{prefix:<24} [{bar:.green/white}] {pos}/{len}") {
+        Ok(pstyle) => Ok(pstyle.progress_chars("#=--")),
+        Err(err) => Err(std::io::Error::other(format!("Failed setting progress style: {err}"))),
+    }
+}
+
+// a line per worker showing what it works on
+pub fn worker_progress_style() -> Result<ProgressStyle, std::io::Error> {
+    match ProgressStyle::with_template("{spinner} {msg}") {
+        Ok(pstyle) => Ok(pstyle),
+        Err(err) => Err(std::io::Error::other(format!("Failed setting progress style: {err}"))),
+    }
+}
+
+// downloads url to path with a progressbar. data goes to <path>.part first, which is resumed if it
+// already exists and renamed to path once the transfer completed and the checksum matched
+pub fn download(url: &str, path: &str, checksum: Checksum) -> Result<(), std::io::Error> {
+    let pb = ProgressBar::new(1);
+    pb.set_style(progress_style()?);
+    download_with(url, path, checksum, &pb)
+}
+
+// like download, but reports to a caller provided progressbar, eg. one attached to a MultiProgress
+pub fn download_with(url: &str, path: &str, checksum: Checksum, pb: &ProgressBar) -> Result<(), std::io::Error> {
+    let part = format!("{path}.part");
+
+    let mut result = transfer(url, &part, pb);
+    // the server does not accept our offset, the partial file is stale
+    if let Err(TransferError::RangeNotSatisfiable) = result {
+        warn!("Partial download {part} does not match {url}, starting over");
+        std::fs::remove_file(&part)?;
+        result = transfer(url, &part, pb);
+    }
+    pb.finish();
+
+    if let Err(err) = result {
+        // keep partial data around for the next attempt, but do not leave empty files behind
+        if std::fs::metadata(&part).map(|meta| meta.len() == 0).unwrap_or_default() {
+            std::fs::remove_file(&part)?;
+        }
+        return Err(err.into_io(url));
+    }
+
+    match checksum {
+        Checksum::Skip => {}
+        Checksum::IfPublished(hash_url) => verify_checksum(hash_url, &part, false)?,
+        Checksum::Required(hash_url) => verify_checksum(hash_url, &part, true)?,
+    }
+
+    std::fs::rename(&part, path)
+}
+
+enum TransferError {
+    RangeNotSatisfiable,
+    Status(u32),
+    Io(std::io::Error),
+    Curl(curl::Error),
+}
+
+impl TransferError {
+    fn into_io(self, url: &str) -> std::io::Error {
+        match self {
+            TransferError::RangeNotSatisfiable => std::io::Error::other(format!("Failed to fetch {url}: range not satisfiable")),
+            TransferError::Status(code) => std::io::Error::other(format!("Failed to fetch {url}: server answered with {code}")),
+            TransferError::Io(err) => std::io::Error::new(err.kind(), format!("Failed to write {url}: {err}")),
+            TransferError::Curl(err) => std::io::Error::other(format!("Failed to fetch {url}: {err}")),
+        }
+    }
+}
+
+fn transfer(url: &str, part: &str, pb: &ProgressBar) -> Result<(), TransferError> {
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(part).map_err(TransferError::Io)?;
+    let offset = file.metadata().map_err(TransferError::Io)?.len();
+
+    let mut easy = Easy::new();
+    easy.url(url).map_err(TransferError::Curl)?;
+    easy.follow_location(true).map_err(TransferError::Curl)?;
+    easy.progress(true).map_err(TransferError::Curl)?;
+    if offset > 0 {
+        info!("Resuming {part} at {offset} bytes");
+        // a plain range instead of resume_from, curl aborts resumes the server answers with the whole file
+        easy.range(&format!("{offset}-")).map_err(TransferError::Curl)?;
+    }
+
+    // status of the last response seen, redirects included
+    let status = Cell::new(0);
+    let truncated = Cell::new(false);
+    let mut write_error = None;
+    let result = {
+        let mut transfer = easy.transfer();
+        transfer
+            .header_function(|header| {
+                let header = String::from_utf8_lossy(header);
+                if header.starts_with("HTTP/") {
+                    status.set(header.split_whitespace().nth(1).and_then(|code| code.parse::<u32>().ok()).unwrap_or_default());
+                }
+                true
+            })
+            .map_err(TransferError::Curl)?;
+        transfer
+            .progress_function(|dl_total, dl_now, _, _| {
+                let base = if status.get() == 206 { offset } else { 0 };
+                if dl_total != 0.0 {
+                    pb.set_length(base + dl_total as u64);
+                }
+                pb.set_position(base + dl_now as u64);
+                true
+            })
+            .map_err(TransferError::Curl)?;
+        transfer
+            .write_function(|data| {
+                match status.get() {
+                    200..=299 => {}
+                    // do not write error pages into the file
+                    _ => return Ok(0),
+                }
+                // the server ignored our range request and sends the whole file
+                if status.get() == 200 && offset > 0 && !truncated.replace(true) {
+                    if let Err(err) = file.set_len(0) {
+                        write_error = Some(err);
+                        return Ok(0);
+                    }
+                }
+                match file.write_all(data) {
+                    Ok(_) => Ok(data.len()),
+                    Err(err) => {
+                        error!("Failed to write content: {}", err);
+                        write_error = Some(err);
+                        // returning less than received aborts the transfer
+                        Ok(0)
+                    }
+                }
+            })
+            .map_err(TransferError::Curl)?;
+        transfer.perform()
+    };
+
+    if let Some(err) = write_error {
+        return Err(TransferError::Io(err));
+    }
+    match status.get() {
+        416 => Err(TransferError::RangeNotSatisfiable),
+        // no response at all, eg. the host could not be resolved or refused the connection
+        0 if result.is_err() => result.map_err(TransferError::Curl),
+        200..=299 => result.map_err(TransferError::Curl),
+        code => Err(TransferError::Status(code)),
+    }
+}
+
+// fetches a small text resource, returns none if the server did not answer with 2xx
+pub fn fetch_text(url: &str) -> Result<Option<String>, std::io::Error> {
+    let mut easy = Easy::new();
+    let mut body = Vec::new();
+
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            body.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer.perform()?;
+    }
+
+    match easy.response_code()? {
+        200..=299 => Ok(Some(String::from_utf8_lossy(&body).trim().to_owned())),
+        code => {
+            trace!("{url} answered with {code}");
+            Ok(None)
+        }
+    }
+}
+
+// asks for the headers of url without its body and returns what they tell about the current content: etag,
+// last-modified and content-length. none if the server answers without 2xx or with neither etag nor last-modified
+pub fn fetch_validator(url: &str) -> Result<Option<String>, std::io::Error> {
+    let mut easy = Easy::new();
+    let mut headers = Vec::new();
+
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    easy.nobody(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.header_function(|header| {
+            let header = String::from_utf8_lossy(header).trim().to_owned();
+            // only the headers of the last response count, redirects included
+            if header.starts_with("HTTP/") {
+                headers.clear();
+            }
+            headers.push(header);
+            true
+        })?;
+        transfer.perform()?;
+    }
+
+    if !(200..=299).contains(&easy.response_code()?) {
+        trace!("{url} answered with {}", easy.response_code()?);
+        return Ok(None);
+    }
+    let find = |name: &str| {
+        headers.iter().find_map(|header| {
+            header
+                .split_once(':')
+                .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| format!("{name}: {}", value.trim()))
+        })
+    };
+    if find("etag").is_none() && find("last-modified").is_none() {
+        trace!("{url} has neither etag nor last-modified");
+        return Ok(None);
+    }
+    Ok(Some(["etag", "last-modified", "content-length"].into_iter().filter_map(find).collect::<Vec<String>>().join("\n")))
+}
+
+// whether the content at url is a local file of size bytes with checksum sha256. the length reported for a HEAD
+// request is compared first, so only content of the same size is downloaded and hashed
+pub fn is_same_content(url: &str, size: u64, sha256: &str) -> Result<bool, std::io::Error> {
+    if fetch_length(url).is_some_and(|length| length != size) {
+        return Ok(false);
+    }
+
+    let mut easy = Easy::new();
+    let mut hasher = Sha256::new();
+    let mut length = 0;
+
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            hasher.update(data);
+            length += data.len() as u64;
+            Ok(data.len())
+        })?;
+        transfer.perform()?;
+    }
+
+    if !(200..=299).contains(&easy.response_code()?) {
+        trace!("{url} answered with {}", easy.response_code()?);
+        return Ok(false);
+    }
+    let actual = hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+    Ok(length == size && actual == sha256.to_lowercase())
+}
+
+// content-length of url, none if the server does not answer a HEAD request with 2xx or does not report it
+fn fetch_length(url: &str) -> Option<u64> {
+    let mut easy = Easy::new();
+    easy.url(url).ok()?;
+    easy.follow_location(true).ok()?;
+    easy.nobody(true).ok()?;
+    easy.perform().ok()?;
+    if !(200..=299).contains(&easy.response_code().ok()?) {
+        return None;
+    }
+    let length = easy.content_length_download().ok()?;
+    (length >= 0.0).then_some(length as u64)
+}
+
+pub fn sha256sum(path: &str) -> Result<String, std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+pub fn sha256_of(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// compares the checksum published at hash_url against the local file, deletes the file on mismatch
+pub fn verify_checksum(hash_url: &str, path: &str, required: bool) -> Result<(), std::io::Error> {
+    let expected = match fetch_text(hash_url)? {
+        Some(expected) if !expected.is_empty() => expected.to_lowercase(),
+        _ if required => return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Master does not publish a checksum for {path}"))),
+        _ => {
+            warn!("Master does not publish a checksum for {path}, skipping verification");
+            return Ok(());
+        }
+    };
+
+    let actual = sha256sum(path)?;
+    if actual != expected {
+        std::fs::remove_file(path)?;
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Checksum mismatch for {path}: expected {expected}, got {actual}"),
+        ));
+    }
+    trace!("Checksum of {path} verified");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use indicatif::ProgressBar;
+
+    use super::{download_with, fetch_validator, is_same_content, sha256_of, Checksum};
+
+    // serves body at /file with an etag, its checksum at /file.sha256 if given and a 500 at every other path. honor_ranges
+    // decides between partial content and the whole file for range requests
+    struct Fixture {
+        url: String,
+        // range header of every request for /file, in order
+        ranges: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl Fixture {
+        fn start(body: &[u8], checksum: Option<String>, honor_ranges: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let ranges = Arc::new(Mutex::new(Vec::new()));
+            let (body, seen) = (body.to_vec(), ranges.clone());
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map(Result::unwrap);
+                    let target = lines.next().unwrap_or_default().split_whitespace().nth(1).unwrap_or_default().to_owned();
+                    let range = lines
+                        .take_while(|line| !line.is_empty())
+                        .find_map(|line| line.to_lowercase().strip_prefix("range: bytes=").map(|range| range.trim_end_matches('-').to_owned()))
+                        .map(|start| start.parse::<usize>().unwrap());
+
+                    let (status, headers, content) = match (target.as_str(), range) {
+                        ("/file", Some(start)) if honor_ranges && start >= body.len() => ("416 Range Not Satisfiable", format!("Content-Range: bytes */{}\r\n", body.len()), Vec::new()),
+                        ("/file", Some(start)) if honor_ranges => (
+                            "206 Partial Content",
+                            format!("Content-Range: bytes {start}-{}/{}\r\n", body.len() - 1, body.len()),
+                            body[start..].to_vec(),
+                        ),
+                        ("/file", _) => ("200 OK", "ETag: \"v1\"\r\n".to_owned(), body.clone()),
+                        ("/file.sha256", _) if checksum.is_some() => ("200 OK", String::new(), checksum.clone().unwrap_or_default().into_bytes()),
+                        ("/file.sha256", _) => ("404 Not Found", String::new(), b"no checksum".to_vec()),
+                        _ => ("500 Internal Server Error", String::new(), b"error page".to_vec()),
+                    };
+                    if target == "/file" {
+                        seen.lock().unwrap().push(range.map(|start| format!("bytes={start}-")));
+                    }
+
+                    let _ = write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n", content.len());
+                    let _ = stream.write_all(&content);
+                }
+            });
+            Self { url, ranges }
+        }
+
+        fn ranges(&self) -> Vec<Option<String>> {
+            self.ranges.lock().unwrap().clone()
+        }
+    }
+
+    fn body() -> Vec<u8> {
+        (0..100_000).map(|index| (index % 251) as u8).collect()
+    }
+
+    // a fresh directory per test, returns the path to download to
+    fn target(name: &str) -> (String, String) {
+        let dir = std::env::temp_dir().join(format!("rranch-download-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file").display().to_string();
+        (path.clone(), format!("{path}.part"))
+    }
+
+    fn exists(path: &str) -> bool {
+        PathBuf::from(path).exists()
+    }
+
+    #[test]
+    fn resumes_partial_download_with_range() {
+        let body = body();
+        let fixture = Fixture::start(&body, None, true);
+        let (path, part) = target("resume");
+        std::fs::write(&part, &body[..30_000]).unwrap();
+
+        download_with(&format!("{}/file", fixture.url), &path, Checksum::Skip, &ProgressBar::hidden()).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(fixture.ranges(), vec![Some("bytes=30000-".to_owned())]);
+        assert!(!exists(&part));
+    }
+
+    #[test]
+    fn starts_over_when_range_is_not_satisfiable() {
+        let body = body();
+        let fixture = Fixture::start(&body, None, true);
+        let (path, part) = target("unsatisfiable");
+        std::fs::write(&part, [body.as_slice(), b"stale"].concat()).unwrap();
+
+        download_with(&format!("{}/file", fixture.url), &path, Checksum::Skip, &ProgressBar::hidden()).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(fixture.ranges(), vec![Some(format!("bytes={}-", body.len() + 5)), None]);
+    }
+
+    #[test]
+    fn replaces_partial_data_when_the_range_is_ignored() {
+        let body = body();
+        let fixture = Fixture::start(&body, None, false);
+        let (path, part) = target("ignored");
+        std::fs::write(&part, b"stale").unwrap();
+
+        download_with(&format!("{}/file", fixture.url), &path, Checksum::Skip, &ProgressBar::hidden()).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(fixture.ranges(), vec![Some("bytes=5-".to_owned())]);
+    }
+
+    #[test]
+    fn failed_download_is_not_renamed() {
+        let fixture = Fixture::start(&body(), None, true);
+        let (path, part) = target("failed");
+
+        assert!(download_with(&format!("{}/missing", fixture.url), &path, Checksum::Skip, &ProgressBar::hidden()).is_err());
+        assert!(!exists(&path));
+        // the error page is not written and the empty part is removed
+        assert!(!exists(&part));
+
+        std::fs::write(&part, b"partial").unwrap();
+        assert!(download_with(&format!("{}/missing", fixture.url), &path, Checksum::Skip, &ProgressBar::hidden()).is_err());
+        assert!(!exists(&path));
+        assert_eq!(std::fs::read(&part).unwrap(), b"partial");
+    }
+
+    #[test]
+    fn connection_errors_are_described_by_curl() {
+        // a port nothing listens on anymore
+        let url = format!("http://{}/file", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+        let (path, _) = target("refused");
+
+        let err = download_with(&url, &path, Checksum::Skip, &ProgressBar::hidden()).unwrap_err().to_string();
+        assert!(!err.contains("answered with"), "{err}");
+        assert!(err.contains("connect"), "{err}");
+    }
+
+    #[test]
+    fn verifies_the_checksum_before_renaming() {
+        let body = body();
+        let fixture = Fixture::start(&body, Some(sha256_of(&body).to_uppercase()), true);
+        let (path, part) = target("checksum");
+
+        download_with(
+            &format!("{}/file", fixture.url),
+            &path,
+            Checksum::Required(&format!("{}/file.sha256", fixture.url)),
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert!(!exists(&part));
+    }
+
+    #[test]
+    fn checksum_mismatch_fails_and_removes_the_download() {
+        let fixture = Fixture::start(&body(), Some(sha256_of(b"something else")), true);
+        let (path, part) = target("mismatch");
+
+        let err = download_with(
+            &format!("{}/file", fixture.url),
+            &path,
+            Checksum::Required(&format!("{}/file.sha256", fixture.url)),
+            &ProgressBar::hidden(),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!exists(&path));
+        assert!(!exists(&part));
+    }
+
+    #[test]
+    fn missing_checksum_fails_only_if_required() {
+        let body = body();
+        let fixture = Fixture::start(&body, None, true);
+        let (path, _) = target("missing");
+        let hash_url = format!("{}/file.sha256", fixture.url);
+
+        assert!(download_with(&format!("{}/file", fixture.url), &path, Checksum::Required(&hash_url), &ProgressBar::hidden()).is_err());
+        assert!(!exists(&path));
+
+        download_with(&format!("{}/file", fixture.url), &path, Checksum::IfPublished(&hash_url), &ProgressBar::hidden()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+    }
+
+    #[test]
+    fn validator_describes_the_content() {
+        let body = body();
+        let fixture = Fixture::start(&body, Some(sha256_of(&body)), true);
+
+        assert_eq!(
+            fetch_validator(&format!("{}/file", fixture.url)).unwrap(),
+            Some(format!("etag: \"v1\"\ncontent-length: {}", body.len()))
+        );
+        // the checksum is served with neither etag nor last-modified
+        assert_eq!(fetch_validator(&format!("{}/file.sha256", fixture.url)).unwrap(), None);
+        assert_eq!(fetch_validator(&format!("{}/missing", fixture.url)).unwrap(), None);
+    }
+
+    #[test]
+    fn compares_remote_content_by_length_and_checksum() {
+        let body = body();
+        let fixture = Fixture::start(&body, None, true);
+        let url = format!("{}/file", fixture.url);
+
+        assert!(is_same_content(&url, body.len() as u64, &sha256_of(&body).to_uppercase()).unwrap());
+        assert!(!is_same_content(&url, body.len() as u64, &sha256_of(b"same length, other content")).unwrap());
+        assert_eq!(fixture.ranges().len(), 4);
+
+        // a different length is known from the HEAD request alone
+        assert!(!is_same_content(&url, 10, &sha256_of(&body)).unwrap());
+        assert_eq!(fixture.ranges().len(), 5);
+
+        assert!(!is_same_content(&format!("{}/missing", fixture.url), body.len() as u64, &sha256_of(&body)).unwrap());
+    }
+}
@@ -1,6 +1,75 @@
-use std::{io::Write, process::Command};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicU8, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use console::{Style, Term};
+use similar::{ChangeTag, TextDiff};
+
+// how prompts are answered, set from --yes / --no-input or RRANCH_YES / RRANCH_NO_INPUT
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interaction {
+    Ask,
+    // yes/no questions are answered with yes, values with their default
+    Yes,
+    // everything is answered with its default, values without one fail
+    NoInput,
+}
+
+static INTERACTION: AtomicU8 = AtomicU8::new(0);
+
+impl Interaction {
+    // --yes wins over --no-input
+    pub fn from_flags(yes: bool, no_input: bool) -> Option<Self> {
+        match (yes, no_input) {
+            (true, _) => Some(Interaction::Yes),
+            (false, true) => Some(Interaction::NoInput),
+            (false, false) => None,
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let set = |name: &str| std::env::var(name).is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"));
+        Self::from_flags(set("RRANCH_YES"), set("RRANCH_NO_INPUT"))
+    }
+}
+
+pub fn get_interaction() -> Interaction {
+    match INTERACTION.load(Ordering::SeqCst) {
+        1 => Interaction::Yes,
+        2 => Interaction::NoInput,
+        _ => Interaction::Ask,
+    }
+}
+
+pub fn set_interaction(interaction: Interaction) {
+    INTERACTION.store(interaction as u8, Ordering::SeqCst)
+}
+
+pub fn is_interactive() -> bool {
+    get_interaction() == Interaction::Ask
+}
+
+// the error for a prompt that has no default while prompts are disabled
+pub fn input_required(text: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Input required for \"{text}\", but prompts are disabled by --yes / --no-input. Pass the value as a flag instead"),
+    )
+}
+
+// an editor waits for the user like a prompt, so it is not opened while prompts are disabled
+pub fn check_editable(path: &str) -> Result<(), std::io::Error> {
+    if is_interactive() {
+        Ok(())
+    } else {
+        Err(input_required(&format!("edit {path}")))
+    }
+}
 
 pub fn get_input() -> Result<String, std::io::Error> {
     let mut input = String::new();
@@ -25,6 +94,18 @@ pub fn get_yn(text: &str, default: bool) -> Result<bool, std::io::Error> {
         )
     };
 
+    match get_interaction() {
+        Interaction::Ask => (),
+        Interaction::Yes => {
+            println!("{question}yes");
+            return Ok(true);
+        }
+        Interaction::NoInput => {
+            println!("{question}{}", if default { "yes" } else { "no" });
+            return Ok(default);
+        }
+    }
+
     loop {
         print!("{question}");
         let input = get_input()?;
@@ -76,7 +157,63 @@ pub fn get_pkgbs(path: &str) -> Result<Vec<String>, std::io::Error> {
     Ok(pkgbs)
 }
 
+// returns (path, description) pairs from a directory or a toml manifest mapping filenames to descriptions
+pub fn get_extra_source_files(path: &str) -> Result<Vec<(String, String)>, std::io::Error> {
+    let path = Path::new(path);
+    let (dir, manifest) = if path.is_dir() {
+        let manifest = path.join("manifest.toml");
+        (path.to_path_buf(), if manifest.is_file() { Some(manifest) } else { None })
+    } else {
+        (path.parent().unwrap_or(Path::new("")).to_path_buf(), Some(path.to_path_buf()))
+    };
+
+    let mut files = Vec::new();
+    match manifest {
+        Some(manifest) => {
+            let descriptions = match toml::from_str::<HashMap<String, String>>(&std::fs::read_to_string(&manifest)?) {
+                Ok(descriptions) => descriptions,
+                Err(err) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse manifest {}: {err}", manifest.display()))),
+            };
+            for (filename, description) in descriptions {
+                let file = dir.join(&filename);
+                if !file.is_file() {
+                    return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} listed in {} is not a file", file.display(), manifest.display())));
+                }
+                files.push((file.display().to_string(), description));
+            }
+        }
+        None => {
+            for entry in std::fs::read_dir(&dir)?.flatten() {
+                if entry.file_type()?.is_file() {
+                    let filename = entry.file_name().to_str().unwrap_or_default().to_owned();
+                    files.push((entry.path().display().to_string(), filename));
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// formats a tar entry like ls -l does, eg. drwxr-xr-x
+pub fn format_mode(entry_type: tar::EntryType, mode: u32) -> String {
+    let kind = match entry_type {
+        tar::EntryType::Directory => 'd',
+        tar::EntryType::Symlink => 'l',
+        tar::EntryType::Char => 'c',
+        tar::EntryType::Block => 'b',
+        tar::EntryType::Fifo => 'p',
+        _ => '-',
+    };
+    let perms = (0..9)
+        .map(|bit| if mode & (0o400 >> bit) != 0 { ['r', 'w', 'x'][bit % 3] } else { '-' })
+        .collect::<String>();
+    format!("{kind}{perms}")
+}
+
+#[allow(clippy::io_other_error)]
 pub fn configure(path: &str, editor: &str) -> Result<(), std::io::Error> {
+    check_editable(path)?;
     let child = Command::new(editor).arg(path).spawn();
 
     match child {
@@ -100,3 +237,108 @@ pub fn truncate_to(input: String, max_chars: usize) -> String {
         s.to_string()
     }
 }
+
+// replaces a leading ~/ with the home directory
+pub fn expand_home(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{}/{rest}", dirs::home_dir().unwrap_or_default().to_str().unwrap_or_default()),
+        None => path.to_owned(),
+    }
+}
+
+// asks for a value, returns the default on empty input
+pub fn get_input_default(text: &str, default: &str) -> Result<String, std::io::Error> {
+    if !is_interactive() {
+        if default.is_empty() {
+            return Err(input_required(text));
+        }
+        println!("{text} [{default}]: {default}");
+        return Ok(default.to_owned());
+    }
+    if default.is_empty() {
+        print!("{text}: ");
+    } else {
+        print!("{text} [{default}]: ");
+    }
+    let input = get_input()?;
+    Ok(if input.is_empty() { default.to_owned() } else { input })
+}
+
+// checks that url is an absolute http(s) url with a host
+pub fn check_url(url: &str) -> Result<(), String> {
+    let rest = match url.split_once("://") {
+        Some(("http" | "https", rest)) => rest,
+        Some((scheme, _)) => return Err(format!("unsupported scheme {scheme}, expected http or https")),
+        None => return Err("missing scheme, expected http:// or https://".to_owned()),
+    };
+    if rest.split(['/', '?']).next().unwrap_or_default().is_empty() {
+        return Err("missing host".to_owned());
+    }
+    if url.contains(char::is_whitespace) {
+        return Err("contains whitespace".to_owned());
+    }
+    Ok(())
+}
+
+// true if the program of a command line like "code -w" is a path to a file or can be found on PATH
+pub fn find_in_path(command: &str) -> bool {
+    let program = command.split_whitespace().next().unwrap_or_default();
+    if program.is_empty() {
+        return false;
+    }
+    if program.contains('/') {
+        return Path::new(&expand_home(program)).is_file();
+    }
+    std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()).any(|dir| dir.join(program).is_file())
+}
+
+// prints a coloured unified diff of two texts, returns false if they are equal
+pub fn print_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> bool {
+    if old == new {
+        return false;
+    }
+    let bold = Style::new().bold();
+    let cyan = Style::new().cyan();
+    let red = Style::new().red();
+    let green = Style::new().green();
+
+    let diff = TextDiff::from_lines(old, new);
+    println!("{}", bold.apply_to(format!("--- {old_name}")));
+    println!("{}", bold.apply_to(format!("+++ {new_name}")));
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        println!("{}", cyan.apply_to(hunk.header()));
+        for change in hunk.iter_changes() {
+            let line = change.to_string_lossy();
+            let line = line.trim_end_matches('\n');
+            match change.tag() {
+                ChangeTag::Delete => println!("{}", red.apply_to(format!("-{line}"))),
+                ChangeTag::Insert => println!("{}", green.apply_to(format!("+{line}"))),
+                ChangeTag::Equal => println!(" {line}"),
+            }
+        }
+    }
+    true
+}
+
+// errors after which the client can not send anything to the master anymore
+pub fn is_connection_lost(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::UnexpectedEof
+    )
+}
+
+// a new directory under the temp dir only the user can access, eg. rranch-bundle-<pid>-<nanos>. it must not exist
+// yet, files in it could have been placed by someone else
+pub fn create_temp_dir(prefix: &str) -> Result<PathBuf, std::io::Error> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let dir = std::env::temp_dir().join(format!("{prefix}-{}-{nanos}", std::process::id()));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&dir)?;
+    Ok(dir)
+}
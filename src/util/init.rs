@@ -0,0 +1,155 @@
+use std::{io::Write, path::Path};
+
+use console::{Style, Term};
+use log::{debug, info, warn};
+use toml::{Table, Value};
+
+use crate::structs::{client::Client, config::config_main::Config};
+
+use super::funcs::{check_url, find_in_path, get_input_default, get_yn, input_required, is_interactive};
+
+// asks for every value needed to talk to a master, tests them against the master and writes them to path.
+// other settings already in the file are kept
+pub fn init(path: &Path, config: &Config) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    let green = Style::new().green();
+    let red = Style::new().red();
+    let master = config.get_master();
+    let client = config.get_client();
+
+    println!("{}", bold.apply_to(format!("Setting up {}", path.display())));
+    // checked before asking anything, the other settings in the file are kept
+    let previous = std::fs::read_to_string(path).ok();
+    let mut table = match previous.as_deref().map(toml::from_str::<Table>) {
+        Some(Ok(table)) => table,
+        Some(Err(err)) => {
+            warn!("{} can not be parsed, its other settings would be lost: {}", path.display(), err.message());
+            if !get_yn("Replace it anyway? The old file is kept as a backup", false)? {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Not replacing {}, fix it or move it away first", path.display())));
+            }
+            Table::new()
+        }
+        None => Table::new(),
+    };
+    let addr = get_input_default("Master address", &master.get_addr())?;
+    let port = loop {
+        let port = get_input_default("Master port", &master.get_port().to_string())?;
+        match port.parse::<u16>() {
+            Ok(port) if port != 0 => break port,
+            // the same default would be answered again
+            _ if !is_interactive() => return Err(invalid_value(&format!("{port} is not a valid port, expected 1 to 65535"))),
+            _ => println!("{} is not a valid port, expected 1 to 65535", red.apply_to(port)),
+        }
+    };
+    // config is the default one if the existing config failed to load, so the env var is checked as well
+    let current = config.get_authkey().ok().or_else(|| std::env::var("RRANCH_AUTHKEY").ok().filter(|authkey| !authkey.is_empty()));
+    let authkey = loop {
+        if !is_interactive() {
+            break current.clone().ok_or_else(|| input_required("Authkey"))?;
+        }
+        if current.is_some() {
+            print!("Authkey (leave empty to keep the current one): ");
+        } else {
+            print!("Authkey: ");
+        }
+        std::io::stdout().flush()?;
+        match (Term::stdout().read_secure_line()?.trim(), &current) {
+            ("", Some(current)) => break current.clone(),
+            ("", None) => println!("{}", red.apply_to("The authkey can not be empty")),
+            (authkey, _) => break authkey.to_owned(),
+        }
+    };
+    let key_file = get_yn("Store the authkey in a separate file only you can read?", true)?;
+    let fetch_url = loop {
+        let fetch_url = get_input_default("Url to fetch packages from", &master.get_fetch_url())?;
+        match check_url(&fetch_url) {
+            Ok(_) => break fetch_url,
+            Err(err) if !is_interactive() => return Err(invalid_value(&format!("{fetch_url} is not a valid url: {err}"))),
+            Err(err) => println!("{} is not a valid url: {err}", red.apply_to(fetch_url)),
+        }
+    };
+    let name = get_input_default("Client name", &client.get_name())?;
+    let editor = loop {
+        let editor = get_input_default("Editor", &client.get_editor())?;
+        if find_in_path(&editor) || get_yn(&format!("{editor} was not found on PATH, use it anyway?"), false)? {
+            break editor;
+        }
+        if !is_interactive() {
+            return Err(invalid_value(&format!("{editor} was not found on PATH")));
+        }
+    };
+
+    print!("Connecting to {addr}:{port}... ");
+    std::io::stdout().flush()?;
+    let result = Client::new(&addr, port).and_then(|mut connection| {
+        let response = connection.auth(&name, &client.get_type(), &authkey, client.get_protver());
+        connection.shutdown()?;
+        response
+    });
+    match result {
+        Ok(response) => {
+            println!("{}", green.apply_to("ok"));
+            debug!("{}", response.logon_message);
+        }
+        Err(err) => {
+            println!("{}", red.apply_to(format!("failed: {err}")));
+            if !get_yn("Save the config anyway?", false)? {
+                return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "Setup aborted, nothing was written"));
+            }
+        }
+    }
+
+    if let Some(file) = &previous {
+        let backup = path.with_extension("toml.bak");
+        write_private(&backup, file)?;
+        info!("Saved the previous config to {}", backup.display());
+    }
+
+    let mut master = table.get("master").and_then(Value::as_table).cloned().unwrap_or_default();
+    master.insert("addr".to_owned(), Value::String(addr));
+    master.insert("port".to_owned(), Value::Integer(port.into()));
+    master.insert("fetch_url".to_owned(), Value::String(fetch_url));
+    master.remove("authkey");
+    master.remove("authkey_file");
+    master.remove("authkey_command");
+    if key_file {
+        let key_path = path.with_file_name("rranch.key");
+        write_private(&key_path, &format!("{authkey}\n"))?;
+        info!("Wrote authkey to {}", key_path.display());
+        master.insert("authkey_file".to_owned(), Value::String(key_path.to_string_lossy().to_string()));
+    } else {
+        master.insert("authkey".to_owned(), Value::String(authkey));
+    }
+    table.insert("master".to_owned(), Value::Table(master));
+
+    let mut client = table.get("client").and_then(Value::as_table).cloned().unwrap_or_default();
+    client.insert("name".to_owned(), Value::String(name));
+    client.insert("editor".to_owned(), Value::String(editor));
+    table.insert("client".to_owned(), Value::Table(client));
+
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new("")))?;
+    write_private(path, &toml::to_string(&table).map_err(std::io::Error::other)?)?;
+    println!("{}", green.apply_to(format!("Wrote {}", path.display())));
+    Ok(())
+}
+
+// a default that is answered while prompts are disabled but can not be used
+fn invalid_value(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{message}. Pass a valid value through its flag or RRANCH_* env var instead"))
+}
+
+// writes a file only the owner can read
+fn write_private(path: &Path, content: &str) -> Result<(), std::io::Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // the mode only applies to new files
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(content.as_bytes())
+}
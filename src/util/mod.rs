@@ -1 +1,9 @@
+pub mod arch;
+pub mod batch;
+pub mod cache;
+pub mod completion;
+pub mod download;
 pub mod funcs;
+pub mod init;
+pub mod shell;
+pub mod source;
@@ -0,0 +1,204 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use console::Style;
+use log::{error, warn};
+use rustyline::{completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::DefaultHistory, validate::Validator, Context, Editor, Helper};
+
+use crate::{
+    args::{
+        cli::{command, try_parse, Cli, Command, Parsed},
+        legacy::translate,
+    },
+    structs::{client::Client, config::config_main::Config},
+};
+
+use super::{
+    completion::{get_candidates, get_kind},
+    funcs::{get_interaction, is_connection_lost, set_interaction},
+};
+
+// set by Ctrl-C while a watch runs in the shell
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// true while a watch runs in the shell, any other command is ended by Ctrl-C like without the handler
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+// runs a command parsed from a shell line
+pub type Handler = fn(&mut Client, &Config, &Command) -> Result<(), std::io::Error>;
+
+// runs a watch loop, Ctrl-C in the shell then makes sleep_interruptible return true instead of exiting
+pub fn interruptible<T>(watch: impl FnOnce() -> T) -> T {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    WATCHING.store(true, Ordering::SeqCst);
+    let result = watch();
+    WATCHING.store(false, Ordering::SeqCst);
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    result
+}
+
+// sleeps for duration, returns true early if Ctrl-C was pressed during an interruptible watch in the shell. outside
+// of it Ctrl-C still exits
+pub fn sleep_interruptible(duration: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        if INTERRUPTED.swap(false, Ordering::SeqCst) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+struct ShellHelper<'a> {
+    config: &'a Config,
+}
+
+impl Completer for ShellHelper<'_> {
+    type Candidate = String;
+
+    // subcommands and flags from the command definitions, names and ids from the master like the completion scripts
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map(|index| index + 1).unwrap_or(0);
+        let word = &line[start..];
+        let words = line[..start].split_whitespace().collect::<Vec<&str>>();
+
+        let mut current = command();
+        for word in &words {
+            if let Some(subcommand) = current.find_subcommand(word) {
+                current = subcommand.clone();
+            }
+        }
+
+        let candidates = match (words.as_slice(), word.starts_with('-')) {
+            (_, true) => current.get_arguments().filter_map(|arg| arg.get_long()).map(|long| format!("--{long}")).collect(),
+            ([group, subcommand], false) if get_kind(group, subcommand).is_some() => {
+                get_kind(group, subcommand).map(|kind| get_candidates(self.config, kind).unwrap_or_default()).unwrap_or_default()
+            }
+            (_, false) => current.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()).map(|subcommand| subcommand.get_name().to_owned()).collect::<Vec<String>>(),
+        };
+        Ok((start, candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect()))
+    }
+}
+
+impl Hinter for ShellHelper<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper<'_> {}
+
+impl Validator for ShellHelper<'_> {}
+
+impl Helper for ShellHelper<'_> {}
+
+// reads commands until exit or Ctrl-D and runs them over the existing connection
+pub fn run(client: &mut Client, config: &Config, handle: Handler) -> Result<(), std::io::Error> {
+    // the handler can not be removed again, so outside of watches it exits with the status of a SIGINT
+    ctrlc::set_handler(|| {
+        if WATCHING.load(Ordering::SeqCst) {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        } else {
+            std::process::exit(130);
+        }
+    })
+    .map_err(std::io::Error::other)?;
+    let mut editor = Editor::<ShellHelper, DefaultHistory>::new().map_err(std::io::Error::other)?;
+    editor.set_helper(Some(ShellHelper { config }));
+    let history = PathBuf::from(config.get_cache().get_dir()).join("shell_history");
+    // there is none on the first start
+    let _ = editor.load_history(&history);
+
+    println!("Commands are the same as on the command line, eg. pkgb checkout zlib. Type help for a list, exit or Ctrl-D to leave");
+    loop {
+        let line = match editor.readline(&get_prompt(client, config)) {
+            Ok(line) => line,
+            // Ctrl-C at the prompt only discards the line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(std::io::Error::other(err)),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line).map_err(std::io::Error::other)?;
+        if line == "exit" || line == "quit" {
+            break;
+        }
+        run_line(client, config, handle, line)?;
+    }
+
+    std::fs::create_dir_all(history.parent().unwrap_or(&history))?;
+    editor.save_history(&history).map_err(std::io::Error::other)
+}
+
+// master name and queue depth, eg. stable (2 queued)>
+fn get_prompt(client: &mut Client, config: &Config) -> String {
+    let name = config.get_master_name();
+    let queued = match client.get_jobs() {
+        Ok(jobs) => jobs.queuedjobs.len().to_string(),
+        Err(_) => "?".to_owned(),
+    };
+    format!("{} ({queued} queued)> ", Style::new().bold().apply_to(name))
+}
+
+// runs a command from a line, --yes, --no-input and --dry-run on the line only apply to it
+pub fn run_command(client: &mut Client, config: &Config, handle: Handler, cli: &Cli) -> Result<(), std::io::Error> {
+    let previous = (get_interaction(), client.dry_run);
+    if let Some(interaction) = cli.get_interaction() {
+        set_interaction(interaction);
+    }
+    client.dry_run |= cli.dry_run;
+    let result = handle(client, config, &cli.command);
+    set_interaction(previous.0);
+    client.dry_run = previous.1;
+    result
+}
+
+// errors of a command are printed and the shell goes on, unless the connection to the master is gone
+fn run_line(client: &mut Client, config: &Config, handle: Handler, line: &str) -> Result<(), std::io::Error> {
+    let words = match shell_words::split(line) {
+        Ok(words) => words,
+        Err(err) => {
+            error!("{err}");
+            return Ok(());
+        }
+    };
+    let argvs = match translate([vec!["rranch".to_owned()], words].concat()) {
+        Ok(argvs) => argvs,
+        Err(err) => {
+            error!("{err}, see help");
+            return Ok(());
+        }
+    };
+
+    for argv in argvs {
+        let Parsed { cli, overrides, name } = match try_parse(argv) {
+            Ok(parsed) => parsed,
+            // also help and usage
+            Err(err) => {
+                err.print()?;
+                return Ok(());
+            }
+        };
+        if !overrides.is_empty() || cli.profile.is_some() {
+            warn!("Config overrides and --profile only apply when starting rranch, ignoring them");
+        }
+        if matches!(cli.command, Command::Shell) {
+            warn!("Already in a shell");
+            continue;
+        }
+
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        if let Err(err) = run_command(client, config, handle, &cli) {
+            if is_connection_lost(&err) {
+                return Err(std::io::Error::new(err.kind(), format!("Lost the connection to the master: {err}")));
+            }
+            error!("Failed on {name}, reason: {err}");
+        }
+    }
+    Ok(())
+}
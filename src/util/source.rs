@@ -0,0 +1,273 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+    },
+};
+
+use curl::easy::Easy;
+use indicatif::HumanBytes;
+use log::{info, trace, warn};
+
+// compressed bytes of a source read at most when guessing its template, large sources are not fetched as a whole
+const GUESS_LIMIT: usize = 16 * 1024 * 1024;
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.xz", ".tar.gz", ".tgz", ".tar.bz2", ".tar.zst", ".tar", ".zip", ".crate"];
+
+// files in the top directory of a source tarball and the template building them, first match wins
+const BUILD_SYSTEMS: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo"),
+    ("meson.build", "meson"),
+    ("CMakeLists.txt", "cmake"),
+    ("configure", "make"),
+    ("Makefile", "make"),
+    ("GNUmakefile", "make"),
+    ("build.ninja", "ninja"),
+];
+
+// the last path segment of url without query and archive extension, eg. zlib-1.2.13
+fn get_stem(url: &str) -> &str {
+    let file = url.split(['?', '#']).next().unwrap_or_default().rsplit('/').next().unwrap_or_default();
+    ARCHIVE_EXTENSIONS.iter().find_map(|ext| file.strip_suffix(ext)).unwrap_or(file)
+}
+
+// guesses the version from tarball names like name-1.2.3.tar.xz, name_1.2.3.tgz or v1.2.3.tar.gz
+pub fn guess_version(pkgname: &str, url: &str) -> Option<String> {
+    let stem = get_stem(url);
+    let version = match stem.strip_prefix(pkgname).and_then(|rest| rest.strip_prefix(['-', '_'])) {
+        Some(version) => version,
+        // the version starts after the first separator followed by a digit
+        None => stem
+            .char_indices()
+            .filter(|(_, c)| *c == '-' || *c == '_')
+            .map(|(index, _)| &stem[index + 1..])
+            .find(|rest| rest.trim_start_matches('v').starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(stem),
+    };
+
+    let version = version.strip_prefix('v').filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit())).unwrap_or(version);
+    if version.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(version.to_owned())
+    } else {
+        None
+    }
+}
+
+// replaces old with new where it stands as a whole version in the path of url, eg. in zlib-1.2.13.tar.xz but not
+// in zlib-11.2.13 or 1.2.130. none if it is nowhere
+pub fn replace_version(url: &str, old: &str, new: &str) -> Option<String> {
+    if old.is_empty() {
+        return None;
+    }
+    // the host is left alone
+    let start = url
+        .find("://")
+        .map(|scheme| url[scheme + 3..].find('/').map(|path| scheme + 3 + path).unwrap_or(url.len()))
+        .unwrap_or(0);
+
+    let mut replaced = url[..start].to_owned();
+    let mut rest = &url[start..];
+    let mut found = false;
+    while let Some(index) = rest.find(old) {
+        let (before, after) = (&rest[..index], &rest[index + old.len()..]);
+        let mut previous = before.chars().rev();
+        let starts = match previous.next() {
+            None => true,
+            // v1.2.3, but not dev1.2.3
+            Some('v') => !previous.next().is_some_and(|c| c.is_ascii_alphanumeric()),
+            Some(c) => !c.is_ascii_alphanumeric() && c != '.',
+        };
+        let mut next = after.chars();
+        let ends = match next.next() {
+            None => true,
+            Some('.') => !next.next().is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => !c.is_ascii_alphanumeric(),
+        };
+
+        replaced.push_str(before);
+        replaced.push_str(if starts && ends { new } else { old });
+        found |= starts && ends;
+        rest = after;
+    }
+    replaced.push_str(rest);
+    found.then_some(replaced)
+}
+
+// guesses the template from the build system files in the source tarball, which is streamed instead of downloaded.
+// crates are always cargo
+pub fn guess_template(url: &str) -> Result<Option<String>, std::io::Error> {
+    if url.contains("crates.io/") || url.split(['?', '#']).next().unwrap_or_default().ends_with(".crate") {
+        return Ok(Some("cargo".to_owned()));
+    }
+
+    let file = url.split(['?', '#']).next().unwrap_or_default().rsplit('/').next().unwrap_or_default();
+    if ![".tar.xz", ".tar.gz", ".tgz", ".tar"].iter().any(|ext| file.ends_with(ext)) {
+        warn!("Can not look into {file}, only .tar, .tar.xz and .tar.gz are supported");
+        return Ok(None);
+    }
+
+    info!("Looking into {url} to guess the template");
+    let truncated = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::sync_channel(16);
+    let files = std::thread::scope(|scope| {
+        let truncated = &truncated;
+        // the sender is dropped with the thread, which ends the body for the reader
+        scope.spawn(move || {
+            if let Err(err) = stream(url, &sender, truncated) {
+                let _ = sender.send(Err(err));
+            }
+        });
+        let reader = ChannelReader { receiver, chunk: Vec::new(), pos: 0 };
+        if file.ends_with(".tar.xz") {
+            list_top_files(xz2::read::XzDecoder::new(reader), truncated)
+        } else if file.ends_with(".tar") {
+            list_top_files(reader, truncated)
+        } else {
+            list_top_files(flate2::read::GzDecoder::new(reader), truncated)
+        }
+    })?;
+
+    trace!("Top level files of {file}: {files:?}");
+    Ok(BUILD_SYSTEMS.iter().find(|(marker, _)| files.contains(*marker)).map(|(_, template)| template.to_string()))
+}
+
+// sends the body of url in chunks until it ends, GUESS_LIMIT is reached or the receiver hangs up
+fn stream(url: &str, sender: &SyncSender<Result<Vec<u8>, std::io::Error>>, truncated: &AtomicBool) -> Result<(), std::io::Error> {
+    let mut easy = Easy::new();
+    easy.url(url)?;
+    easy.follow_location(true)?;
+
+    let status = Cell::new(0);
+    let mut total = 0;
+    let mut hung_up = false;
+    let result = {
+        let mut transfer = easy.transfer();
+        transfer.header_function(|header| {
+            let header = String::from_utf8_lossy(header);
+            if header.starts_with("HTTP/") {
+                status.set(header.split_whitespace().nth(1).and_then(|code| code.parse::<u32>().ok()).unwrap_or_default());
+            }
+            true
+        })?;
+        transfer.write_function(|data| {
+            if !(200..=299).contains(&status.get()) {
+                return Ok(0);
+            }
+            total += data.len();
+            if total > GUESS_LIMIT {
+                truncated.store(true, Ordering::SeqCst);
+                return Ok(0);
+            }
+            match sender.send(Ok(data.to_vec())) {
+                Ok(_) => Ok(data.len()),
+                Err(_) => {
+                    hung_up = true;
+                    Ok(0)
+                }
+            }
+        })?;
+        transfer.perform()
+    };
+
+    if hung_up || truncated.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    if !(200..=299).contains(&status.get()) {
+        return Err(std::io::Error::other(format!("Failed to fetch {url}: server answered with {}", status.get())));
+    }
+    Ok(result?)
+}
+
+// reads what stream sends, the end of the channel is the end of the body
+struct ChannelReader {
+    receiver: Receiver<Result<Vec<u8>, std::io::Error>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// names of files at most one directory deep in the tarball, eg. zlib-1.2.13/configure. stops once the first of
+// BUILD_SYSTEMS is found, nothing else would be picked over it. a source cut off at GUESS_LIMIT gives what was seen
+fn list_top_files(reader: impl Read, truncated: &AtomicBool) -> Result<HashSet<String>, std::io::Error> {
+    let mut files = HashSet::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) if truncated.load(Ordering::SeqCst) => break,
+            Err(err) => return Err(err),
+        };
+        let entry_path = entry.path()?;
+        let components = entry_path.components().collect::<Vec<_>>();
+        if components.len() <= 2 {
+            if let Some(name) = entry_path.file_name() {
+                files.insert(name.to_string_lossy().to_string());
+            }
+        }
+        if files.contains(BUILD_SYSTEMS[0].0) {
+            break;
+        }
+    }
+
+    if truncated.load(Ordering::SeqCst) {
+        warn!("Only looked at the first {} of the source", HumanBytes(GUESS_LIMIT as u64));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::replace_version;
+
+    #[test]
+    fn replaces_whole_versions_in_the_path() {
+        assert_eq!(
+            replace_version("https://zlib.net/fossils/zlib-1.2.13.tar.gz", "1.2.13", "1.3"),
+            Some("https://zlib.net/fossils/zlib-1.3.tar.gz".to_owned())
+        );
+        assert_eq!(
+            replace_version("https://download.gnome.org/sources/glib/2.78/glib-2.78.0.tar.xz", "2.78.0", "2.78.1"),
+            Some("https://download.gnome.org/sources/glib/2.78/glib-2.78.1.tar.xz".to_owned())
+        );
+        assert_eq!(
+            replace_version("https://github.com/htop-dev/htop/archive/refs/tags/v3.2.2.tar.gz", "3.2.2", "3.3.0"),
+            Some("https://github.com/htop-dev/htop/archive/refs/tags/v3.3.0.tar.gz".to_owned())
+        );
+        assert_eq!(
+            replace_version("https://www.kernel.org/pub/linux/utils/util-linux/v2.39/util-linux-2.39.tar.xz", "2.39", "2.40"),
+            Some("https://www.kernel.org/pub/linux/utils/util-linux/v2.40/util-linux-2.40.tar.xz".to_owned())
+        );
+    }
+
+    #[test]
+    fn leaves_partial_matches_and_the_host_alone() {
+        assert_eq!(replace_version("https://example.org/foo-11.2.tar.gz", "1.2", "1.3"), None);
+        assert_eq!(replace_version("https://example.org/foo-1.2.13.tar.gz", "1.2", "1.3"), None);
+        assert_eq!(replace_version("https://example.org/foo-dev1.2.tar.gz", "1.2", "1.3"), None);
+        assert_eq!(replace_version("https://mirror-1.2.example.org/foo.tar.gz", "1.2", "1.3"), None);
+        assert_eq!(
+            replace_version("https://mirror-1.2.example.org/foo-1.2.tar.gz", "1.2", "1.3"),
+            Some("https://mirror-1.2.example.org/foo-1.3.tar.gz".to_owned())
+        );
+        assert_eq!(replace_version("https://example.org/foo.tar.gz", "", "1.3"), None);
+    }
+}